@@ -1,189 +1,2467 @@
-use anyhow::{anyhow, Result};
-use nom::lib::std::fmt::Formatter;
-use nom::{
-    branch::alt,
-    bytes::complete::{tag, take},
-    character::complete::{digit1, one_of},
-    combinator::{map, map_res, opt, peek, value, verify},
-    multi::many0,
-    sequence::{pair, preceded, terminated, tuple},
-};
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::str;
-
-pub fn decode(bencoded_str: &[u8]) -> Result<BEncodedType> {
-    let (rest, parsed) = parse_primitive(bencoded_str).unwrap();
-
-    if rest.len() > 0 {
-        return Err(anyhow!("File has trailing data: {:?}", rest));
-    }
-    return Ok(parsed);
-}
-
-#[derive(PartialEq)]
+use crate::alloc_prelude::*;
+use crate::error::{Error, Result};
+use crate::{sha1, sha256};
+use alloc::collections::BTreeMap;
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::convert::{Infallible, TryFrom};
+use core::fmt::{Debug, Display, Formatter};
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::str;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+#[cfg(not(any(feature = "nom", feature = "minimal")))]
+compile_error!("either the `nom` (default) or the `minimal` feature must be enabled");
+
+// The nom parser is the default; `minimal` swaps in a hand-written one with
+// the same interface for builds that can't take the dependency.
+#[cfg(feature = "minimal")]
+mod minimal_parser;
+#[cfg(feature = "minimal")]
+use minimal_parser as parser;
+#[cfg(not(feature = "minimal"))]
+mod nom_parser;
+#[cfg(not(feature = "minimal"))]
+use nom_parser as parser;
+
+use parser::{parse_primitive, parse_str};
+
+mod diff;
+pub use diff::{diff, DiffEntry, DiffKind};
+
+/// How deeply lists and dictionaries may nest before `decode` gives up.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// How many values, counted across the whole tree, `decode` will build.
+pub const DEFAULT_MAX_ENTRIES: usize = 4_000_000;
+
+/// Bounds on what the decoder is willing to build, so hostile input fails
+/// cleanly instead of exhausting the stack or memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeLimits {
+    /// How deeply lists and dictionaries may nest.
+    pub max_depth: usize,
+    /// How many values may be parsed, counted across the whole tree.
+    pub max_entries: usize,
+    /// The longest string (including dictionary keys) that will be accepted.
+    pub max_string_len: usize,
+    /// Reject encodings BEP-3 forbids but that are tolerated by default,
+    /// like string lengths with leading zeros.
+    pub strict: bool,
+}
+
+impl DecodeLimits {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn with_max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = max_string_len;
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            // Strings borrow from the input, so they can't outgrow it.
+            max_string_len: usize::MAX,
+            strict: false,
+        }
+    }
+}
+
+/// Decodes a whole buffer, which can be anything that derefs to bytes.  The
+/// result borrows from it.
+///
+/// ```
+/// use tquery::bencode::{decode, BEncodedType, BencodeError};
+///
+/// let contents = vec![b'i', b'5', b'e'];
+/// assert_eq!(decode(&contents)?, BEncodedType::Integer(5));
+/// assert_eq!(decode("i5e")?, BEncodedType::Integer(5));
+/// assert_eq!(decode(b"i5e")?, BEncodedType::Integer(5));
+/// # Ok::<(), BencodeError>(())
+/// ```
+pub fn decode<T: AsRef<[u8]> + ?Sized>(input: &T) -> Result<BEncodedType<'_>, BencodeError> {
+    decode_with(input.as_ref(), &DecodeLimits::default())
+}
+
+/// Like `decode`, but fails with a `BencodeError` as soon as the input
+/// exceeds any of the given `limits`.
+pub fn decode_with<'a>(
+    bencoded_str: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<BEncodedType<'a>, BencodeError> {
+    let (parsed, rest) = parse_one_with(bencoded_str, limits)?;
+
+    if !rest.is_empty() {
+        return Err(BencodeError::TrailingData {
+            offset: bencoded_str.len() - rest.len(),
+        });
+    }
+    Ok(parsed)
+}
+
+/// Parses the first value in `buf`, returning it along with whatever input
+/// follows it.  Unlike `decode`, trailing data is not an error, which suits
+/// protocol messages where a bencoded value is followed by a binary payload.
+pub fn parse_one(buf: &[u8]) -> Result<(BEncodedType<'_>, &[u8]), BencodeError> {
+    parse_one_with(buf, &DecodeLimits::default())
+}
+
+/// Decodes a buffer holding any number of values back to back, like several
+/// torrents concatenated into one archive.  The decode limits apply to the
+/// buffer as a whole, and error offsets count from its start.  An empty
+/// buffer holds no values.
+pub fn decode_all(buf: &[u8]) -> Result<Vec<BEncodedType<'_>>, BencodeError> {
+    let state = ParseState::new(DecodeLimits::default());
+    let mut values = Vec::new();
+    let mut input = buf;
+    while !input.is_empty() {
+        let (rest, parsed) =
+            parse_primitive(input, 0, &state).map_err(|e| parser::to_bencode_error(e, buf))?;
+        values.push(parsed);
+        input = rest;
+    }
+    Ok(values)
+}
+
+/// Like `decode`, but salvages what it can from a damaged buffer: the value
+/// at its start if that's complete, along with the error `decode` would
+/// have reported.  A value cut off partway, like a dictionary missing its
+/// closing `e`, gives `None`.  When values are concatenated, the first one
+/// is returned and the rest is reported as trailing data.
+pub fn decode_partial(buf: &[u8]) -> (Option<BEncodedType<'_>>, Option<BencodeError>) {
+    match parse_one(buf) {
+        Ok((parsed, [])) => (Some(parsed), None),
+        Ok((parsed, rest)) => {
+            let offset = buf.len() - rest.len();
+            (Some(parsed), Some(BencodeError::TrailingData { offset }))
+        }
+        Err(e) => (None, Some(e)),
+    }
+}
+
+/// Something `decode_lenient` tolerated that a conforming encoder wouldn't
+/// have written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Warning {
+    /// Where the offending value starts.
+    pub offset: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    /// A dictionary's keys weren't in sorted order.
+    UnsortedKeys,
+    /// An integer was padded with leading zeros, like `i03e`.
+    LeadingZeroInteger,
+    /// More input followed the value, and was ignored.
+    TrailingData,
+    /// A UTF-8 byte order mark or whitespace came before the value, and was
+    /// skipped.
+    LeadingJunk,
+}
+
+/// Like `decode`, but accepts minor spec violations, returning them as
+/// warnings alongside the value.  Anything that can't be parsed at all is
+/// still an error.
+pub fn decode_lenient(buf: &[u8]) -> Result<(BEncodedType<'_>, Vec<Warning>), BencodeError> {
+    let state = ParseState::lenient(DecodeLimits::default());
+    let start = skip_leading_junk(buf);
+    if start.len() < buf.len() {
+        state.warn(WarningKind::LeadingJunk, buf);
+    }
+    if start.is_empty() {
+        return Err(BencodeError::UnexpectedEof);
+    }
+    let (rest, parsed) =
+        parse_primitive(start, 0, &state).map_err(|e| parser::to_bencode_error(e, buf))?;
+    if !rest.is_empty() {
+        state.warn(WarningKind::TrailingData, rest);
+    }
+    let warnings = state
+        .warnings
+        .into_inner()
+        .into_iter()
+        .map(|(kind, remaining)| Warning {
+            offset: buf.len() - remaining,
+            kind,
+        })
+        .collect();
+    Ok((parsed, warnings))
+}
+
+/// Skips a UTF-8 byte order mark and ASCII whitespace, which some tools
+/// prepend to files they write.
+fn skip_leading_junk(buf: &[u8]) -> &[u8] {
+    let buf = buf.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(buf);
+    let len = buf.iter().take_while(|x| x.is_ascii_whitespace()).count();
+    &buf[len..]
+}
+
+/// Like `parse_one`, but returns how many bytes the value took up rather
+/// than the remaining input, for callers framing a message by offsets.
+pub fn decode_prefix(buf: &[u8]) -> Result<(BEncodedType<'_>, usize), BencodeError> {
+    let (parsed, rest) = parse_one(buf)?;
+    Ok((parsed, buf.len() - rest.len()))
+}
+
+fn parse_one_with<'a>(
+    buf: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<(BEncodedType<'a>, &'a [u8]), BencodeError> {
+    if buf.is_empty() {
+        return Err(BencodeError::UnexpectedEof);
+    }
+    let state = ParseState::new(*limits);
+    let (rest, parsed) =
+        parse_primitive(buf, 0, &state).map_err(|e| parser::to_bencode_error(e, buf))?;
+    Ok((parsed, rest))
+}
+
+/// A dictionary key along with the encoded bytes of its value.
+pub type RawEntry<'a> = (&'a [u8], &'a [u8]);
+
+/// The entries of the dictionary at the start of `buf`, with each value left
+/// as the exact bytes it was encoded with.  Useful when a value has to be
+/// hashed or copied verbatim, like a torrent's `info` dictionary.
+pub fn raw_dict_entries(buf: &[u8]) -> Result<Vec<RawEntry<'_>>, BencodeError> {
+    let state = ParseState::new(DecodeLimits::default());
+    let mut input = match buf.split_first() {
+        Some((b'd', rest)) => rest,
+        _ => return Err(BencodeError::NotADictionary),
+    };
+    let mut entries = Vec::new();
+    while input.first() != Some(&b'e') {
+        let (value_start, key) = parse_str(input).map_err(|e| parser::to_bencode_error(e, buf))?;
+        let (rest, _) = parse_primitive(value_start, 1, &state)
+            .map_err(|e| parser::to_bencode_error(e, buf))?;
+        entries.push((key, &value_start[..value_start.len() - rest.len()]));
+        input = rest;
+    }
+    Ok(entries)
+}
+
+/// How `encode_with` lays out dictionaries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeOptions {
+    /// Sort every dictionary's keys and drop repeated keys, keeping the last
+    /// occurrence, as the spec requires.  Otherwise entries are written in
+    /// the order they're stored.  Only canonical output is guaranteed to
+    /// give a hand-built `info` dictionary the info-hash other clients
+    /// compute for it.
+    pub canonical: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions { canonical: true }
+    }
+}
+
+/// Serializes `value` to bencode, sorting dictionary keys and dropping
+/// repeated ones (keeping the last) as the spec requires.  The same as
+/// `encode_with` with the default, canonical, options.
+pub fn encode(value: &BEncodedType) -> Vec<u8> {
+    encode_with(value, &EncodeOptions::default())
+}
+
+/// Like `encode`, but with control over dictionary key order.  `decode`
+/// keeps dictionaries in the order they were read, so non-canonical output
+/// reproduces a parsed tree's key order, repeated keys included.
+pub fn encode_with(value: &BEncodedType, options: &EncodeOptions) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match encode_into(value, &mut buf, options.canonical) {
+        Ok(()) => buf,
+        Err(never) => match never {},
+    }
+}
+
+/// Like `encode`, but streams the output to `w` instead of building it up
+/// in memory.
+#[cfg(feature = "std")]
+pub fn encode_to<W: Write>(value: &BEncodedType, w: &mut W) -> io::Result<()> {
+    encode_into(value, &mut IoSink(w), true)
+}
+
+/// Where `encode_into` writes, so encoding doesn't depend on `std::io`.
+trait Sink {
+    type Error;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl Sink for Vec<u8> {
+    type Error = Infallible;
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Infallible> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+struct IoSink<'w, W>(&'w mut W);
+
+#[cfg(feature = "std")]
+impl<W: Write> Sink for IoSink<'_, W> {
+    type Error = io::Error;
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.0.write_all(bytes)
+    }
+}
+
+fn encode_into<S: Sink>(
+    value: &BEncodedType,
+    out: &mut S,
+    canonical: bool,
+) -> Result<(), S::Error> {
+    match value {
+        BEncodedType::String(x) => encode_str(x, out),
+        BEncodedType::Integer(x) => out.put(format!("i{}e", x).as_bytes()),
+        BEncodedType::List(x) => {
+            out.put(b"l")?;
+            for item in x {
+                encode_into(item, out, canonical)?;
+            }
+            out.put(b"e")
+        }
+        BEncodedType::Dictionary(x) => {
+            let (mut stored, mut canonical_order) = (x.iter(), x.iter_canonical());
+            let entries: &mut dyn Iterator<Item = _> = if canonical {
+                &mut canonical_order
+            } else {
+                &mut stored
+            };
+            out.put(b"d")?;
+            for (key, value) in entries {
+                encode_str(key, out)?;
+                encode_into(value, out, canonical)?;
+            }
+            out.put(b"e")
+        }
+    }
+}
+
+fn encode_str<S: Sink>(x: &[u8], out: &mut S) -> Result<(), S::Error> {
+    out.put(format!("{}:", x.len()).as_bytes())?;
+    out.put(x)
+}
+
+fn encoded_str_len(x: &[u8]) -> usize {
+    decimal_len(x.len() as u64) + 1 + x.len()
+}
+
+/// How many digits `n` has in decimal.
+fn decimal_len(mut n: u64) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BencodeError {
+    /// The input isn't valid bencode, starting at byte `offset`.
+    Malformed {
+        offset: usize,
+    },
+    /// A complete value was parsed, but more input follows at `offset`.
+    TrailingData {
+        offset: usize,
+    },
+    /// The input ended before a value started, e.g. an empty file.
+    UnexpectedEof,
+    NotADictionary,
+    DepthExceeded,
+    TooManyEntries,
+    StringTooLong(usize),
+    /// A string's length prefix claims more bytes than the input has left.
+    BadStringLength(usize),
+}
+
+impl Display for BencodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BencodeError::Malformed { offset } => {
+                write!(f, "Malformed bencode at byte {}", offset)
+            }
+            BencodeError::TrailingData { offset } => {
+                write!(f, "File has trailing data at byte {}", offset)
+            }
+            BencodeError::UnexpectedEof => f.write_str("Unexpected end of input"),
+            BencodeError::NotADictionary => f.write_str("Not a dictionary"),
+            BencodeError::DepthExceeded => f.write_str("Nesting exceeds the maximum depth"),
+            BencodeError::TooManyEntries => f.write_str("Too many values in one document"),
+            BencodeError::StringTooLong(len) => {
+                write!(f, "String of {} bytes exceeds the maximum length", len)
+            }
+            BencodeError::BadStringLength(len) => {
+                write!(f, "String of {} bytes runs past the end of the input", len)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BencodeError {}
+
+/// Decodes a whole buffer, exactly like `decode`.
+///
+/// ```
+/// use std::convert::TryInto;
+/// use tquery::bencode::{BEncodedType, BencodeError};
+///
+/// let bytes: &[u8] = b"li5e3:fooe";
+/// let value: BEncodedType = bytes.try_into()?;
+/// assert_eq!(value.as_list().unwrap().len(), 2);
+///
+/// let value: BEncodedType = "i42e".try_into()?;
+/// assert_eq!(value, BEncodedType::Integer(42));
+/// # Ok::<(), BencodeError>(())
+/// ```
+impl<'a> TryFrom<&'a [u8]> for BEncodedType<'a> {
+    type Error = BencodeError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        decode(value)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for BEncodedType<'a> {
+    type Error = BencodeError;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        decode(value.as_bytes())
+    }
+}
+
+/// A dictionary's entries, in the order they were decoded or built in.
+/// It derefs to a slice of them, and keeps an index in key order alongside
+/// so `get` is a binary search either way.
+#[derive(Clone, Default)]
+pub struct DictRepr<'a> {
+    entries: Vec<(&'a [u8], BEncodedType<'a>)>,
+    /// Positions in `entries` ordered by key, with repeated keys in the
+    /// order they appear.  `None` if `entries` is already in key order, as
+    /// it is for spec-conforming input, so that costs nothing extra.
+    by_key: Option<Vec<usize>>,
+}
+
+impl<'a> DictRepr<'a> {
+    /// Whether the entries are stored in key order, repeats aside.
+    pub fn in_key_order(&self) -> bool {
+        self.by_key.is_none()
+    }
+
+    /// The value for `key`.  If a key is repeated, the last occurrence
+    /// wins.
+    pub fn get(&self, key: &[u8]) -> Option<&BEncodedType<'a>> {
+        let not_after = |k: &[u8]| bencode_key_cmp(k, key) != Ordering::Greater;
+        let end = match &self.by_key {
+            None => self.entries.partition_point(|(k, _)| not_after(k)),
+            Some(by_key) => by_key.partition_point(|&i| not_after(self.entries[i].0)),
+        };
+        let (k, value) = self.nth_by_key(end.checked_sub(1)?);
+        (*k == key).then_some(value)
+    }
+
+    /// The entries ordered by key, with repeated keys in the order they
+    /// appear.
+    pub fn iter_by_key(&self) -> impl Iterator<Item = &(&'a [u8], BEncodedType<'a>)> + '_ {
+        (0..self.entries.len()).map(move |n| self.nth_by_key(n))
+    }
+
+    /// `iter_by_key` with only the last of any repeated key, which is what
+    /// canonical encoding writes.
+    pub fn iter_canonical(&self) -> impl Iterator<Item = &(&'a [u8], BEncodedType<'a>)> + '_ {
+        let len = self.entries.len();
+        (0..len)
+            .filter(move |&n| n + 1 == len || self.nth_by_key(n).0 != self.nth_by_key(n + 1).0)
+            .map(move |n| self.nth_by_key(n))
+    }
+
+    /// The values, for editing them in place.  Keys can't change this way,
+    /// so the index stays valid.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut BEncodedType<'a>> {
+        self.entries.iter_mut().map(|(_, value)| value)
+    }
+
+    /// The entries, in the order they were stored in.
+    pub fn into_vec(self) -> Vec<(&'a [u8], BEncodedType<'a>)> {
+        self.entries
+    }
+
+    fn nth_by_key(&self, n: usize) -> &(&'a [u8], BEncodedType<'a>) {
+        match &self.by_key {
+            None => &self.entries[n],
+            Some(by_key) => &self.entries[by_key[n]],
+        }
+    }
+}
+
+impl<'a> From<Vec<(&'a [u8], BEncodedType<'a>)>> for DictRepr<'a> {
+    fn from(entries: Vec<(&'a [u8], BEncodedType<'a>)>) -> Self {
+        let in_key_order = entries
+            .windows(2)
+            .all(|w| bencode_key_cmp(w[0].0, w[1].0) != Ordering::Greater);
+        let by_key = if in_key_order {
+            None
+        } else {
+            // Stable, so repeated keys stay in the order they appear.
+            let mut by_key: Vec<usize> = (0..entries.len()).collect();
+            by_key.sort_by(|&a, &b| bencode_key_cmp(entries[a].0, entries[b].0));
+            Some(by_key)
+        };
+        DictRepr { entries, by_key }
+    }
+}
+
+/// Keeps the entries in the order given, repeats and all.
+impl<'a> FromIterator<(&'a [u8], BEncodedType<'a>)> for DictRepr<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a [u8], BEncodedType<'a>)>>(entries: I) -> Self {
+        entries.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<'a> core::ops::Deref for DictRepr<'a> {
+    type Target = [(&'a [u8], BEncodedType<'a>)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl<'b, 'a> IntoIterator for &'b DictRepr<'a> {
+    type Item = &'b (&'a [u8], BEncodedType<'a>);
+    type IntoIter = core::slice::Iter<'b, (&'a [u8], BEncodedType<'a>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// Equal if they hold the same entries, whatever order they're stored in.
+impl PartialEq for DictRepr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter_by_key().eq(other.iter_by_key())
+    }
+}
+
+impl Debug for DictRepr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Cloning only copies the tree's structure; strings still borrow from the
+/// same input buffer.
+#[derive(Clone)]
 pub enum BEncodedType<'a> {
     String(&'a [u8]),
     Integer(i64),
     List(Vec<BEncodedType<'a>>),
-    Dictionary(HashMap<&'a [u8], BEncodedType<'a>>),
+    Dictionary(DictRepr<'a>),
+}
+
+impl From<i64> for BEncodedType<'_> {
+    fn from(value: i64) -> Self {
+        BEncodedType::Integer(value)
+    }
+}
+
+impl<'a> FromIterator<BEncodedType<'a>> for BEncodedType<'a> {
+    fn from_iter<I: IntoIterator<Item = BEncodedType<'a>>>(items: I) -> Self {
+        BEncodedType::List(items.into_iter().collect())
+    }
+}
+
+/// Collects into a dictionary sorted by key.  If a key is repeated, the last
+/// occurrence wins and the others are dropped, so the result encodes to a
+/// valid dictionary.
+impl<'a> FromIterator<(&'a [u8], BEncodedType<'a>)> for BEncodedType<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a [u8], BEncodedType<'a>)>>(entries: I) -> Self {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        sort_dedup_entries(&mut entries);
+        BEncodedType::Dictionary(entries.into())
+    }
+}
+
+/// Iterates over a list's items.  Any other value has no items, so a `for`
+/// loop over it runs zero times; use `as_list` to treat that as an error.
+impl<'b, 'a> IntoIterator for &'b BEncodedType<'a> {
+    type Item = &'b BEncodedType<'a>;
+    type IntoIter = core::slice::Iter<'b, BEncodedType<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            BEncodedType::List(items) => items.iter(),
+            _ => [].iter(),
+        }
+    }
 }
 
-impl<'a> BEncodedType<'a> {
-    pub fn type_str(&self) -> &'static str {
-        match self {
-            &Self::String(_) => "String",
-            &Self::Integer(_) => "Integer",
-            &Self::List(_) => "List",
-            &Self::Dictionary(_) => "Dictionary",
+impl<'a> BEncodedType<'a> {
+    /// A string value.  There's no `From<&str>` since `TryFrom<&str>`
+    /// already means "decode this".
+    pub fn string(value: &'a str) -> Self {
+        BEncodedType::String(value.as_bytes())
+    }
+
+    pub fn bytes(value: &'a [u8]) -> Self {
+        BEncodedType::String(value)
+    }
+
+    pub fn list<I: IntoIterator<Item = BEncodedType<'a>>>(items: I) -> Self {
+        items.into_iter().collect()
+    }
+
+    /// A dictionary with its entries sorted by key, as `encode` writes them.
+    pub fn dict<K, I>(entries: I) -> Self
+    where
+        K: AsRef<[u8]> + ?Sized + 'a,
+        I: IntoIterator<Item = (&'a K, BEncodedType<'a>)>,
+    {
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.as_ref(), value))
+            .collect()
+    }
+
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            Self::String(_) => "String",
+            Self::Integer(_) => "Integer",
+            Self::List(_) => "List",
+            Self::Dictionary(_) => "Dictionary",
+        }
+    }
+
+    /// The error for asking this value to be something it isn't.
+    pub(crate) fn wrong_type(&self, expected: &'static str) -> Error {
+        Error::WrongType {
+            expected,
+            found: self.type_str(),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&'a str> {
+        if let BEncodedType::String(x) = self {
+            str::from_utf8(x).map_err(Error::Utf8)
+        } else {
+            Err(self.wrong_type("a string"))
+        }
+    }
+
+    /// Like `as_str`, but replaces invalid UTF-8 with U+FFFD instead of
+    /// failing.
+    pub fn as_str_lossy(&self) -> Result<Cow<'a, str>> {
+        Ok(String::from_utf8_lossy(self.as_str_bytes()?))
+    }
+
+    pub fn as_str_bytes(&self) -> Result<&'a [u8]> {
+        if let BEncodedType::String(x) = self {
+            Ok(x)
+        } else {
+            Err(self.wrong_type("a string"))
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64> {
+        if let BEncodedType::Integer(x) = self {
+            Ok(*x)
+        } else {
+            Err(self.wrong_type("an integer"))
+        }
+    }
+
+    /// The integer as a `u32`, failing if it's negative or too big.
+    pub fn as_u32(&self) -> Result<u32> {
+        let x = self.as_int()?;
+        u32::try_from(x).map_err(|_| Error::OutOfRange {
+            value: x,
+            target: "u32",
+        })
+    }
+
+    /// The integer as a `usize`, failing if it's negative or too big.
+    pub fn as_usize(&self) -> Result<usize> {
+        let x = self.as_int()?;
+        usize::try_from(x).map_err(|_| Error::OutOfRange {
+            value: x,
+            target: "usize",
+        })
+    }
+
+    pub fn as_list(&self) -> Result<&[BEncodedType<'a>]> {
+        if let BEncodedType::List(x) = self {
+            Ok(x)
+        } else {
+            Err(self.wrong_type("a list"))
+        }
+    }
+
+    /// The number of items in a list or entries in a dictionary, or `None`
+    /// for strings and integers.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            BEncodedType::List(x) => Some(x.len()),
+            BEncodedType::Dictionary(x) => Some(x.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether a list or dictionary is empty, or `None` for strings and
+    /// integers.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// The dictionary's entries, for doing several lookups with
+    /// `dict_lookup` without checking the type each time.
+    pub fn as_dict(&self) -> Result<&DictRepr<'a>> {
+        if let BEncodedType::Dictionary(x) = self {
+            Ok(x)
+        } else {
+            Err(self.wrong_type("a dictionary"))
+        }
+    }
+
+    pub fn dict_keys(&self) -> Result<Vec<&'a str>> {
+        self.as_dict()?
+            .iter()
+            .map(|(key, _value)| str::from_utf8(key).map_err(Error::Utf8))
+            .collect()
+    }
+
+    pub fn dict_get(&self, key: &str) -> Result<&BEncodedType<'a>> {
+        self.dict_get_opt(key)?.ok_or(Error::KeyNotFound)
+    }
+
+    /// Like `dict_get`, but an absent key is `Ok(None)` rather than an
+    /// error, which only happens if this isn't a dictionary.
+    pub fn dict_get_opt(&self, key: &str) -> Result<Option<&BEncodedType<'a>>> {
+        Ok(dict_lookup(self.as_dict()?, key))
+    }
+
+    /// Sorts every dictionary's keys and drops repeated keys, keeping the
+    /// last occurrence, so the tree itself is in the canonical form `encode`
+    /// writes, even if it came from `decode_lenient` or was built by hand.
+    pub fn canonicalize(&mut self) {
+        match self {
+            BEncodedType::List(items) => items.iter_mut().for_each(BEncodedType::canonicalize),
+            BEncodedType::Dictionary(entries) => {
+                let mut sorted = core::mem::take(entries).into_vec();
+                sort_dedup_entries(&mut sorted);
+                *entries = sorted.into();
+                entries.values_mut().for_each(BEncodedType::canonicalize);
+            }
+            BEncodedType::String(_) | BEncodedType::Integer(_) => {}
+        }
+    }
+
+    /// A copy of this torrent with the `info.pieces` hashes and the v2
+    /// `piece layers` replaced by placeholders like `<redacted 12 pieces>`,
+    /// for sharing its structure without the bulky hashes.
+    pub fn redacted(&self) -> OwnedBEncoded {
+        let mut owned = OwnedBEncoded::from(self);
+        if let OwnedBEncoded::Dictionary(root) = &mut owned {
+            if let Some(OwnedBEncoded::Dictionary(info)) = root.get_mut(&b"info"[..]) {
+                if let Some(pieces) = info.get_mut(&b"pieces"[..]) {
+                    redact_hashes(pieces, sha1::DIGEST_LEN);
+                }
+            }
+            if let Some(layers) = root.get_mut(&b"piece layers"[..]) {
+                redact_hashes(layers, sha256::DIGEST_LEN);
+            }
+        }
+        owned
+    }
+
+    /// An owned copy of this tree with `f` applied to every string value,
+    /// e.g. to rewrite tracker URLs.  Dictionary keys are left as they are.
+    pub fn map_strings<F: FnMut(&[u8]) -> Vec<u8>>(self, mut f: F) -> OwnedBEncoded {
+        map_strings_with(&self, &mut f)
+    }
+
+    /// Looks up a value by a JSON-Pointer-like path such as
+    /// `/info/files/0/length`, the form `diff` reports paths in.  Segments
+    /// index lists when they're numbers and are keys otherwise, with `~1`
+    /// for `/` and `~0` for `~`.  `""` is the value itself.
+    pub fn pointer(&self, path: &str) -> Option<&BEncodedType<'a>> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        let mut value = self;
+        for segment in path.strip_prefix('/')?.split('/') {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            value = match value {
+                BEncodedType::List(items) => items.get(segment.parse::<usize>().ok()?)?,
+                BEncodedType::Dictionary(entries) => dict_lookup(entries, &segment)?,
+                BEncodedType::String(_) | BEncodedType::Integer(_) => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Converts to a `serde_json::Value`.  Integers become numbers, lists
+    /// arrays and dictionaries objects, with invalid UTF-8 in keys replaced
+    /// by U+FFFD.  Strings that are valid UTF-8 become JSON strings, and
+    /// any other string becomes `{"__bytes_hex__": "..."}`.  That's meant
+    /// to be converted back, so unlike the `{"hex": "..."}` of the CLI's
+    /// `query::to_json` it uses a key no real dictionary is likely to have.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{Map, Value};
+        match self {
+            BEncodedType::String(x) => match utf8_or_hex(x) {
+                Ok(text) => Value::from(text),
+                Err(hex) => {
+                    let mut object = Map::new();
+                    object.insert("__bytes_hex__".to_string(), Value::from(hex));
+                    Value::Object(object)
+                }
+            },
+            BEncodedType::Integer(x) => Value::from(*x),
+            BEncodedType::List(items) => Value::Array(items.iter().map(Self::to_json).collect()),
+            BEncodedType::Dictionary(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| {
+                        (String::from_utf8_lossy(key).into_owned(), value.to_json())
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Counts what's in this tree, e.g. to pick `DecodeLimits` or spot a
+    /// pathologically nested input.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        self.collect_stats(0, &mut stats);
+        stats
+    }
+
+    /// How many bytes `encode` would write for this value, worked out
+    /// without building the output.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            BEncodedType::String(x) => encoded_str_len(x),
+            BEncodedType::Integer(x) => {
+                let sign = if *x < 0 { 1 } else { 0 };
+                2 + sign + decimal_len(x.unsigned_abs())
+            }
+            BEncodedType::List(items) => 2 + items.iter().map(Self::encoded_len).sum::<usize>(),
+            BEncodedType::Dictionary(entries) => {
+                let body: usize = entries
+                    .iter_canonical()
+                    .map(|(key, value)| encoded_str_len(key) + value.encoded_len())
+                    .sum();
+                2 + body
+            }
+        }
+    }
+
+    fn collect_stats(&self, depth: usize, stats: &mut TreeStats) {
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        match self {
+            BEncodedType::String(x) => stats.total_string_bytes += x.len(),
+            BEncodedType::Integer(_) => {}
+            BEncodedType::List(items) => {
+                stats.list_count += 1;
+                stats.max_depth = stats.max_depth.max(depth + 1);
+                for item in items {
+                    item.collect_stats(depth + 1, stats);
+                }
+            }
+            BEncodedType::Dictionary(entries) => {
+                stats.dict_count += 1;
+                stats.max_depth = stats.max_depth.max(depth + 1);
+                for (key, value) in entries {
+                    stats.total_string_bytes += key.len();
+                    value.collect_stats(depth + 1, stats);
+                }
+            }
+        }
+    }
+
+    /// Follows `path` through nested dictionaries and reads an integer, so
+    /// `get_i64(&["info", "length"])` stands in for a chain of `dict_get`s.
+    /// Errors are prefixed with the path up to the segment that failed.
+    pub fn get_i64(&self, path: &[&str]) -> Result<i64> {
+        let value = self.get_path(path)?;
+        value.as_int().map_err(|e| path_error(path, e))
+    }
+
+    /// Like `get_i64`, but reads a UTF-8 string.
+    pub fn get_str(&self, path: &[&str]) -> Result<&'a str> {
+        let value = self.get_path(path)?;
+        value.as_str().map_err(|e| path_error(path, e))
+    }
+
+    /// Like `get_i64`, but reads a string's raw bytes.
+    pub fn get_bytes(&self, path: &[&str]) -> Result<&'a [u8]> {
+        let value = self.get_path(path)?;
+        value.as_str_bytes().map_err(|e| path_error(path, e))
+    }
+
+    fn get_path(&self, path: &[&str]) -> Result<&BEncodedType<'a>> {
+        let mut value = self;
+        for (i, key) in path.iter().enumerate() {
+            value = value
+                .dict_get(key)
+                .map_err(|e| path_error(&path[..=i], e))?;
+        }
+        Ok(value)
+    }
+}
+
+/// What `BEncodedType::stats` found in a tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Every value, counted the way `DecodeLimits::max_entries` counts them.
+    pub nodes: usize,
+    /// How deeply lists and dictionaries nest, as `DecodeLimits::max_depth`
+    /// measures it: 0 for a lone string or integer.
+    pub max_depth: usize,
+    pub dict_count: usize,
+    pub list_count: usize,
+    /// The length of every string, dictionary keys included.
+    pub total_string_bytes: usize,
+}
+
+fn path_error(path: &[&str], e: Error) -> Error {
+    Error::AtPath {
+        path: path.join("/"),
+        source: Box::new(e),
+    }
+}
+
+/// `map_strings` by reference, so the recursion can share `f`.
+fn map_strings_with<F: FnMut(&[u8]) -> Vec<u8>>(value: &BEncodedType, f: &mut F) -> OwnedBEncoded {
+    match value {
+        BEncodedType::String(x) => OwnedBEncoded::String(f(x)),
+        BEncodedType::Integer(x) => OwnedBEncoded::Integer(*x),
+        BEncodedType::List(items) => {
+            OwnedBEncoded::List(items.iter().map(|item| map_strings_with(item, f)).collect())
+        }
+        BEncodedType::Dictionary(entries) => OwnedBEncoded::Dictionary(
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_vec(), map_strings_with(value, f)))
+                .collect(),
+        ),
+    }
+}
+
+/// Replaces a string of concatenated hashes, or a dictionary of them, with
+/// a placeholder saying how many there were.
+fn redact_hashes(value: &mut OwnedBEncoded, hash_len: usize) {
+    let hash_bytes = match value {
+        OwnedBEncoded::String(x) => x.len(),
+        OwnedBEncoded::Dictionary(layers) => layers
+            .values()
+            .map(|layer| match layer {
+                OwnedBEncoded::String(x) => x.len(),
+                _ => 0,
+            })
+            .sum(),
+        _ => return,
+    };
+    let placeholder = format!("<redacted {} pieces>", hash_bytes / hash_len);
+    *value = OwnedBEncoded::String(placeholder.into_bytes());
+}
+
+/// The order BEP-3 requires dictionary keys to be in: raw bytes compared as
+/// unsigned, so `a` < `aa` < `b` and every ASCII byte sorts before `0x80`.
+/// That's neither locale nor UTF-16 order, and sorting any other way
+/// changes a torrent's info-hash.
+pub fn bencode_key_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+/// Sorts entries by key, keeping only the last occurrence of each key.
+fn sort_dedup_entries(entries: &mut Vec<(&[u8], BEncodedType)>) {
+    // Reversed, the stable sort puts each key's last occurrence first,
+    // which is the one `dedup_by_key` keeps.
+    entries.reverse();
+    entries.sort_by(|a, b| bencode_key_cmp(a.0, b.0));
+    entries.dedup_by_key(|(key, _)| *key);
+}
+
+/// Binary searches a dictionary's entries for `key`.  If a key is
+/// repeated, the last occurrence wins.
+pub fn dict_lookup<'b, 'a>(entries: &'b DictRepr<'a>, key: &str) -> Option<&'b BEncodedType<'a>> {
+    entries.get(key.as_bytes())
+}
+
+/// A string as JSON can hold it: the text if it's valid UTF-8, or else its
+/// hex for the caller to tag.  Shared by both JSON conversions, which only
+/// differ in the tag.
+pub(crate) fn utf8_or_hex(x: &[u8]) -> core::result::Result<&str, String> {
+    str::from_utf8(x).map_err(|_| sha1::to_hex(x))
+}
+
+impl PartialEq for BEncodedType<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BEncodedType::String(a), BEncodedType::String(b)) => a == b,
+            (BEncodedType::Integer(a), BEncodedType::Integer(b)) => a == b,
+            (BEncodedType::List(a), BEncodedType::List(b)) => a == b,
+            (BEncodedType::Dictionary(a), BEncodedType::Dictionary(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for BEncodedType<'_> {}
+
+impl Hash for BEncodedType<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            BEncodedType::String(x) => x.hash(state),
+            BEncodedType::Integer(x) => x.hash(state),
+            BEncodedType::List(x) => x.hash(state),
+            BEncodedType::Dictionary(x) => {
+                // Hashed like the slice of entries in key order would be.
+                x.len().hash(state);
+                x.iter_by_key().for_each(|entry| entry.hash(state));
+            }
+        }
+    }
+}
+
+impl Debug for BEncodedType<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BEncodedType::String(x) => {
+                let parsed = str::from_utf8(x)
+                    .map(|y| y.to_owned())
+                    .unwrap_or_else(|_e| {
+                        x.iter().map(|y| format!("{:02X?}", y)).collect::<String>()
+                    });
+                f.write_str(&parsed)
+            }
+            BEncodedType::Integer(x) => f.write_str(&format!("{}", x)),
+            BEncodedType::List(x) => f.debug_list().entries(x).finish(),
+            BEncodedType::Dictionary(x) => {
+                let mut fmt_mapper = f.debug_map();
+                for pair in x.iter() {
+                    let key = String::from_utf8_lossy(pair.0);
+                    fmt_mapper.key(&key);
+                    fmt_mapper.value(&pair.1);
+                }
+                fmt_mapper.finish()
+            }
+        }
+    }
+}
+
+/// How many bytes of a non UTF-8 string `Display` shows before eliding.
+const DISPLAY_HEX_PREVIEW: usize = 8;
+
+impl Display for BEncodedType<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl BEncodedType<'_> {
+    fn fmt_indented(&self, f: &mut Formatter<'_>, indent: usize) -> core::fmt::Result {
+        match self {
+            BEncodedType::String(x) => match str::from_utf8(x) {
+                Ok(s) => f.write_str(s),
+                Err(_) => {
+                    write!(f, "<{} bytes: ", x.len())?;
+                    for byte in x.iter().take(DISPLAY_HEX_PREVIEW) {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                    if x.len() > DISPLAY_HEX_PREVIEW {
+                        f.write_str("...")?;
+                    }
+                    f.write_str(">")
+                }
+            },
+            BEncodedType::Integer(x) => write!(f, "{}", x),
+            BEncodedType::List(x) if x.is_empty() => f.write_str("[]"),
+            BEncodedType::List(x) => {
+                f.write_str("[\n")?;
+                for item in x {
+                    write!(f, "{:width$}", "", width = (indent + 1) * 2)?;
+                    item.fmt_indented(f, indent + 1)?;
+                    f.write_str("\n")?;
+                }
+                write!(f, "{:width$}]", "", width = indent * 2)
+            }
+            BEncodedType::Dictionary(x) if x.is_empty() => f.write_str("{}"),
+            BEncodedType::Dictionary(x) => {
+                f.write_str("{\n")?;
+                for (key, value) in x.iter_by_key() {
+                    write!(
+                        f,
+                        "{:width$}{}: ",
+                        "",
+                        String::from_utf8_lossy(key),
+                        width = (indent + 1) * 2
+                    )?;
+                    value.fmt_indented(f, indent + 1)?;
+                    f.write_str("\n")?;
+                }
+                write!(f, "{:width$}}}", "", width = indent * 2)
+            }
+        }
+    }
+}
+
+/// A bencoded value that owns its data, for building or editing trees that
+/// can't borrow from an input buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedBEncoded {
+    String(Vec<u8>),
+    Integer(i64),
+    List(Vec<OwnedBEncoded>),
+    Dictionary(BTreeMap<Vec<u8>, OwnedBEncoded>),
+}
+
+impl OwnedBEncoded {
+    /// A borrowing view of this tree, e.g. for passing to `encode`.
+    pub fn as_borrowed(&self) -> BEncodedType<'_> {
+        match self {
+            OwnedBEncoded::String(x) => BEncodedType::String(x),
+            OwnedBEncoded::Integer(x) => BEncodedType::Integer(*x),
+            OwnedBEncoded::List(x) => BEncodedType::List(x.iter().map(Self::as_borrowed).collect()),
+            OwnedBEncoded::Dictionary(x) => BEncodedType::Dictionary(
+                x.iter()
+                    .map(|(k, v)| (k.as_slice(), v.as_borrowed()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<&BEncodedType<'_>> for OwnedBEncoded {
+    fn from(value: &BEncodedType<'_>) -> Self {
+        match value {
+            BEncodedType::String(x) => OwnedBEncoded::String(x.to_vec()),
+            BEncodedType::Integer(x) => OwnedBEncoded::Integer(*x),
+            BEncodedType::List(x) => OwnedBEncoded::List(x.iter().map(Self::from).collect()),
+            BEncodedType::Dictionary(x) => OwnedBEncoded::Dictionary(
+                x.iter().map(|(k, v)| (k.to_vec(), Self::from(v))).collect(),
+            ),
+        }
+    }
+}
+
+/// The parser's error type: either a syntax error at some position, or an
+/// error like a decode limit that should be reported as-is.
+#[derive(Debug, PartialEq)]
+enum ParseError<'a> {
+    Syntax(parser::Error<&'a [u8]>),
+    Limit(BencodeError),
+}
+
+impl<'a> ParseError<'a> {
+    fn into_bencode_error(self, input: &[u8]) -> BencodeError {
+        match self {
+            ParseError::Syntax(e) => BencodeError::Malformed {
+                offset: input.len() - e.input.len(),
+            },
+            ParseError::Limit(e) => e,
+        }
+    }
+}
+
+type IResult<'a, T> = Result<(&'a [u8], T), parser::Err<ParseError<'a>>>;
+
+/// The limits in effect for one decode, plus how much of them has been used.
+struct ParseState {
+    limits: DecodeLimits,
+    entries: Cell<usize>,
+    lenient: bool,
+    /// What lenient mode let through, with how much input was left at each
+    /// point, since the parsers only see what's left.
+    warnings: RefCell<Vec<(WarningKind, usize)>>,
+}
+
+impl ParseState {
+    fn new(limits: DecodeLimits) -> Self {
+        ParseState {
+            limits,
+            entries: Cell::new(0),
+            lenient: false,
+            warnings: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn lenient(limits: DecodeLimits) -> Self {
+        ParseState {
+            lenient: true,
+            ..ParseState::new(limits)
+        }
+    }
+
+    fn warn(&self, kind: WarningKind, at: &[u8]) {
+        self.warnings.borrow_mut().push((kind, at.len()));
+    }
+
+    /// Accounts for one more value, failing once `max_entries` is used up.
+    fn take_entry(&self) -> Result<(), parser::Err<ParseError<'static>>> {
+        let entries = self.entries.get() + 1;
+        if entries > self.limits.max_entries {
+            return Err(limit_error(BencodeError::TooManyEntries));
+        }
+        self.entries.set(entries);
+        Ok(())
+    }
+
+    /// Accounts for opening a list or dictionary at `depth`, returning the
+    /// depth of its children.
+    fn descend(&self, depth: usize) -> Result<usize, parser::Err<ParseError<'static>>> {
+        if depth >= self.limits.max_depth {
+            return Err(limit_error(BencodeError::DepthExceeded));
+        }
+        Ok(depth + 1)
+    }
+
+    /// In strict mode, rejects a string at `input` whose length has a
+    /// leading zero.  `0:` itself is fine.
+    fn check_length_prefix<'a>(&self, input: &'a [u8]) -> Result<(), parser::Err<ParseError<'a>>> {
+        if self.limits.strict && input.len() > 1 && input[0] == b'0' && input[1] != b':' {
+            let e = parser::Error::new(input, parser::ErrorKind::Digit);
+            return Err(parser::Err::Failure(ParseError::Syntax(e)));
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, s: &[u8]) -> Result<(), parser::Err<ParseError<'static>>> {
+        if s.len() > self.limits.max_string_len {
+            return Err(limit_error(BencodeError::StringTooLong(s.len())));
+        }
+        Ok(())
+    }
+}
+
+fn limit_error(e: BencodeError) -> parser::Err<ParseError<'static>> {
+    parser::Err::Failure(ParseError::Limit(e))
+}
+
+/// Dictionaries are supposed to come in with sorted keys.  They're kept in
+/// the order they came in regardless, so non-canonical encoding can
+/// reproduce it, but unsorted ones get a warning.  `input` is where the
+/// dictionary started.
+fn check_dict_order(dict: &DictRepr, input: &[u8], state: &ParseState) {
+    if !dict.in_key_order() {
+        state.warn(WarningKind::UnsortedKeys, input);
+    }
+}
+
+/// `parse_int`, except that in lenient mode integers with leading zeros
+/// are accepted, with a warning, instead of failing.
+fn parse_int_with<'a>(input: &'a [u8], state: &ParseState) -> IResult<'a, i64> {
+    match parser::parse_int(input) {
+        Err(parser::Err::Failure(e)) if state.lenient => match parse_padded_int(input) {
+            Some((rest, x)) => {
+                state.warn(WarningKind::LeadingZeroInteger, input);
+                Ok((rest, x))
+            }
+            None => Err(parser::Err::Failure(e)),
+        },
+        result => result,
+    }
+}
+
+fn parse_padded_int(input: &[u8]) -> Option<(&[u8], i64)> {
+    let body = input.strip_prefix(b"i")?;
+    let end = body.iter().position(|&x| x == b'e')?;
+    let digits = &body[..end];
+    let unsigned = digits.strip_prefix(b"-").unwrap_or(digits);
+    // `str::parse` would take a leading `+`, which BEP-3 doesn't allow.
+    if unsigned.is_empty() || !unsigned.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let x = str::from_utf8(digits).ok()?.parse().ok()?;
+    Some((&body[end + 1..], x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parser::{parse_dictionary, parse_int, parse_list, ErrorKind};
+    use super::*;
+
+    fn syntax_error<T>(remaining: &[u8], kind: ErrorKind) -> IResult<'_, T> {
+        Err(parser::Err::Error(ParseError::Syntax(parser::Error::new(
+            remaining, kind,
+        ))))
+    }
+
+    fn syntax_failure<T>(remaining: &[u8], kind: ErrorKind) -> IResult<'_, T> {
+        Err(parser::Err::Failure(ParseError::Syntax(
+            parser::Error::new(remaining, kind),
+        )))
+    }
+
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut buf = vec![b'l'; depth];
+        buf.extend(vec![b'e'; depth]);
+        buf
+    }
+
+    mod decode_with {
+        use super::*;
+
+        #[test]
+        fn reports_trailing_data() {
+            assert_eq!(
+                decode(b"i1ei2e"),
+                Err(BencodeError::TrailingData { offset: 3 })
+            );
+        }
+
+        #[test]
+        fn empty_input() {
+            assert_eq!(decode(b""), Err(BencodeError::UnexpectedEof));
+        }
+
+        #[test]
+        fn whitespace_only() {
+            assert_eq!(decode(b" \n\t"), Err(BencodeError::Malformed { offset: 0 }));
+        }
+
+        #[test]
+        fn reports_malformed_offset() {
+            assert_eq!(decode(b"li1ex"), Err(BencodeError::Malformed { offset: 4 }));
+        }
+
+        #[test]
+        fn shallow_nesting_succeeds() {
+            let buf = nested_lists(50);
+            assert!(decode(&buf).is_ok());
+        }
+
+        #[test]
+        fn deep_nesting_fails_under_default() {
+            let buf = nested_lists(1000);
+            let err = decode(&buf).unwrap_err();
+            assert_eq!(err, BencodeError::DepthExceeded);
+        }
+
+        #[test]
+        fn depth_is_enforced() {
+            let buf = nested_lists(3);
+            let limits = DecodeLimits::default().with_max_depth(3);
+            assert!(decode_with(&buf, &limits).is_ok());
+            let limits = DecodeLimits::default().with_max_depth(2);
+            let err = decode_with(&buf, &limits).unwrap_err();
+            assert_eq!(err, BencodeError::DepthExceeded);
+        }
+
+        #[test]
+        fn entry_count_is_enforced() {
+            // One list plus five integers is six values.
+            let buf = b"li1ei2ei3ei4ei5ee";
+            let limits = DecodeLimits::default().with_max_entries(6);
+            assert!(decode_with(buf, &limits).is_ok());
+            let limits = DecodeLimits::default().with_max_entries(5);
+            let err = decode_with(buf, &limits).unwrap_err();
+            assert_eq!(err, BencodeError::TooManyEntries);
+        }
+
+        #[test]
+        fn entries_are_counted_across_nesting() {
+            let buf = b"d1:ali1eli2eee1:bi3ee";
+            let limits = DecodeLimits::default().with_max_entries(6);
+            assert!(decode_with(buf, &limits).is_ok());
+            let limits = DecodeLimits::default().with_max_entries(5);
+            assert!(decode_with(buf, &limits).is_err());
+        }
+
+        #[test]
+        fn string_length_is_enforced() {
+            let buf = b"l3:foo6:foobare";
+            let limits = DecodeLimits::default().with_max_string_len(6);
+            assert!(decode_with(buf, &limits).is_ok());
+            let limits = DecodeLimits::default().with_max_string_len(5);
+            let err = decode_with(buf, &limits).unwrap_err();
+            assert_eq!(err, BencodeError::StringTooLong(6));
+        }
+
+        #[test]
+        fn string_length_applies_to_keys() {
+            let buf = b"d6:foobari1ee";
+            let limits = DecodeLimits::default().with_max_string_len(5);
+            assert!(decode_with(buf, &limits).is_err());
+        }
+
+        #[test]
+        fn limits_are_independent() {
+            let buf = b"ll6:foobaree";
+            let limits = DecodeLimits {
+                max_depth: 2,
+                max_entries: 3,
+                max_string_len: 6,
+                strict: false,
+            };
+            assert!(decode_with(buf, &limits).is_ok());
+            assert!(decode_with(buf, &limits.with_max_depth(1)).is_err());
+            assert!(decode_with(buf, &limits.with_max_entries(2)).is_err());
+            assert!(decode_with(buf, &limits.with_max_string_len(5)).is_err());
+        }
+    }
+
+    mod strict {
+        use super::*;
+
+        fn strict() -> DecodeLimits {
+            DecodeLimits::default().with_strict(true)
+        }
+
+        #[test]
+        fn empty_string() {
+            assert_eq!(decode_with(b"0:", &strict()), Ok(BEncodedType::String(b"")));
+        }
+
+        #[test]
+        fn plain_length() {
+            assert_eq!(
+                decode_with(b"3:foo", &strict()),
+                Ok(BEncodedType::String(b"foo"))
+            );
+        }
+
+        #[test]
+        fn rejects_leading_zero() {
+            assert_eq!(
+                decode_with(b"03:foo", &strict()),
+                Err(BencodeError::Malformed { offset: 0 })
+            );
+            assert_eq!(
+                decode_with(b"d03:fooi1ee", &strict()),
+                Err(BencodeError::Malformed { offset: 1 })
+            );
+        }
+
+        #[test]
+        fn lenient_by_default() {
+            assert_eq!(decode(b"03:foo"), Ok(BEncodedType::String(b"foo")));
+        }
+    }
+
+    mod decode_lenient {
+        use super::*;
+
+        #[test]
+        fn unsorted_keys() {
+            let buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:fooee";
+            let unsorted = b"d4:infod6:lengthi4e4:name3:fooe8:announce1:ae";
+            let (value, warnings) = decode_lenient(unsorted).unwrap();
+            assert_eq!(value, decode(buf).unwrap());
+            assert_eq!(
+                warnings,
+                vec![Warning {
+                    offset: 0,
+                    kind: WarningKind::UnsortedKeys
+                }]
+            );
+        }
+
+        #[test]
+        fn nested_unsorted_keys() {
+            let (_, warnings) = decode_lenient(b"d1:ad1:bi1e1:ai2eee").unwrap();
+            assert_eq!(
+                warnings,
+                vec![Warning {
+                    offset: 4,
+                    kind: WarningKind::UnsortedKeys
+                }]
+            );
+        }
+
+        #[test]
+        fn leading_zero_integer() {
+            let (value, warnings) = decode_lenient(b"li1ei-03ee").unwrap();
+            assert_eq!(
+                value,
+                BEncodedType::list(vec![BEncodedType::Integer(1), BEncodedType::Integer(-3)])
+            );
+            assert_eq!(
+                warnings,
+                vec![Warning {
+                    offset: 4,
+                    kind: WarningKind::LeadingZeroInteger
+                }]
+            );
+            assert!(decode(b"li1ei-03ee").is_err());
+        }
+
+        #[test]
+        fn byte_order_mark() {
+            let buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:fooee";
+            let prefixed = [&b"\xEF\xBB\xBF"[..], buf].concat();
+            let (value, warnings) = decode_lenient(&prefixed).unwrap();
+            assert_eq!(value, decode(buf).unwrap());
+            assert_eq!(
+                warnings,
+                vec![Warning {
+                    offset: 0,
+                    kind: WarningKind::LeadingJunk
+                }]
+            );
+            assert!(decode(&prefixed).is_err());
+        }
+
+        #[test]
+        fn leading_whitespace() {
+            let buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:fooee";
+            let prefixed = [&b" \r\n\t"[..], buf].concat();
+            let (value, warnings) = decode_lenient(&prefixed).unwrap();
+            assert_eq!(value, decode(buf).unwrap());
+            assert_eq!(warnings[0].kind, WarningKind::LeadingJunk);
+            assert!(decode(&prefixed).is_err());
+        }
+
+        #[test]
+        fn only_junk() {
+            assert_eq!(
+                decode_lenient(b"\xEF\xBB\xBF  ").unwrap_err(),
+                BencodeError::UnexpectedEof
+            );
+        }
+
+        #[test]
+        fn trailing_data() {
+            let (value, warnings) = decode_lenient(b"i1exyz").unwrap();
+            assert_eq!(value, BEncodedType::Integer(1));
+            assert_eq!(
+                warnings,
+                vec![Warning {
+                    offset: 3,
+                    kind: WarningKind::TrailingData
+                }]
+            );
+        }
+
+        #[test]
+        fn conforming_input_has_no_warnings() {
+            let (_, warnings) = decode_lenient(b"d1:ai1e1:bli2eee").unwrap();
+            assert!(warnings.is_empty());
+        }
+
+        #[test]
+        fn still_fails_on_malformed_input() {
+            assert_eq!(
+                decode_lenient(b"li1e"),
+                Err(BencodeError::Malformed { offset: 4 })
+            );
+            assert!(decode_lenient(b"ixe").is_err());
+        }
+    }
+
+    mod into_iterator {
+        use super::*;
+
+        #[test]
+        fn list_items() {
+            let value = decode(b"li1ei2ei3ee").unwrap();
+            let mut total = 0;
+            for item in &value {
+                total += item.as_int().unwrap();
+            }
+            assert_eq!(total, 6);
+        }
+
+        #[test]
+        fn non_list_is_empty() {
+            let value = decode(b"d1:ali1eee").unwrap();
+            let mut count = 0;
+            for _ in &value {
+                count += 1;
+            }
+            assert_eq!(count, 0);
+        }
+    }
+
+    mod as_str_lossy {
+        use super::*;
+
+        #[test]
+        fn replaces_invalid_utf8() {
+            let value = BEncodedType::String(b"caf\xFF");
+            assert!(value.as_str().is_err());
+            assert_eq!(value.as_str_lossy().unwrap(), "caf\u{FFFD}");
+        }
+
+        #[test]
+        fn borrows_valid_utf8() {
+            let value = BEncodedType::string("café");
+            assert!(matches!(
+                value.as_str_lossy().unwrap(),
+                Cow::Borrowed("café")
+            ));
+        }
+
+        #[test]
+        fn not_a_string() {
+            assert!(BEncodedType::Integer(1).as_str_lossy().is_err());
+        }
+    }
+
+    mod dict_get {
+        use super::*;
+
+        #[test]
+        fn finds_every_key() {
+            // Written out of order to exercise the key index.
+            let keys: Vec<String> = (0..200).rev().map(|i| format!("key{}", i)).collect();
+            let mut buf = b"d".to_vec();
+            for (i, key) in keys.iter().enumerate() {
+                buf.extend_from_slice(format!("{}:{}i{}e", key.len(), key, i).as_bytes());
+            }
+            buf.push(b'e');
+            let value = decode(&buf).unwrap();
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(
+                    value.dict_get(key).unwrap(),
+                    &BEncodedType::Integer(i as i64)
+                );
+            }
+        }
+
+        #[test]
+        fn repeated_key_out_of_order() {
+            let (value, _) = decode_lenient(b"d1:bi1e1:ai2e1:bi3e1:ai4ee").unwrap();
+            assert_eq!(value.dict_get("a").unwrap(), &BEncodedType::Integer(4));
+            assert_eq!(value.dict_get("b").unwrap(), &BEncodedType::Integer(3));
+            assert!(value.dict_get("c").is_err());
+            let keys: Vec<&[u8]> = value.as_dict().unwrap().iter().map(|(k, _)| *k).collect();
+            assert_eq!(keys, [b"b", b"a", b"b", b"a"]);
+        }
+
+        #[test]
+        fn repeated_key_in_order() {
+            let value = decode(b"d1:ai1e1:ai2e1:bi3ee").unwrap();
+            assert!(value.as_dict().unwrap().in_key_order());
+            assert_eq!(value.dict_get("a").unwrap(), &BEncodedType::Integer(2));
+        }
+
+        #[test]
+        fn missing_key() {
+            let value = decode(b"d1:ai1e1:ci3ee").unwrap();
+            assert!(value.dict_get("b").is_err());
+            assert!(value.dict_get("").is_err());
+            assert!(value.dict_get("d").is_err());
+        }
+
+        #[test]
+        fn last_duplicate_wins() {
+            let value = decode(b"d1:ai1e1:bi2e1:ai3ee").unwrap();
+            assert_eq!(value.dict_get("a").unwrap(), &BEncodedType::Integer(3));
+        }
+
+        #[test]
+        fn not_a_dictionary() {
+            assert!(BEncodedType::Integer(1).dict_get("a").is_err());
+        }
+    }
+
+    mod as_u32 {
+        use super::*;
+
+        #[test]
+        fn valid() {
+            assert_eq!(BEncodedType::Integer(16384).as_u32().unwrap(), 16384);
+            assert_eq!(BEncodedType::Integer(16384).as_usize().unwrap(), 16384);
+        }
+
+        #[test]
+        fn negative() {
+            let err = BEncodedType::Integer(-5).as_u32().unwrap_err();
+            assert_eq!(err.to_string(), "integer -5 is not a valid u32");
+            assert!(BEncodedType::Integer(-5).as_usize().is_err());
+        }
+
+        #[test]
+        fn too_big() {
+            let value = BEncodedType::Integer(i64::from(u32::MAX) + 1);
+            assert!(value.as_u32().is_err());
+        }
+
+        #[test]
+        fn not_an_integer() {
+            assert!(BEncodedType::String(b"5").as_u32().is_err());
+        }
+    }
+
+    mod len {
+        use super::*;
+
+        #[test]
+        fn empty_list() {
+            let value = decode(b"le").unwrap();
+            assert_eq!(value.len(), Some(0));
+            assert_eq!(value.is_empty(), Some(true));
+        }
+
+        #[test]
+        fn list() {
+            let value = decode(b"li1ei2ei3ee").unwrap();
+            assert_eq!(value.len(), Some(3));
+            assert_eq!(value.is_empty(), Some(false));
+        }
+
+        #[test]
+        fn dictionary() {
+            let value = decode(b"d1:ai1e1:bi2ee").unwrap();
+            assert_eq!(value.len(), Some(2));
+        }
+
+        #[test]
+        fn scalar() {
+            assert_eq!(BEncodedType::Integer(5).len(), None);
+            assert_eq!(BEncodedType::Integer(5).is_empty(), None);
+        }
+    }
+
+    mod dict_get_opt {
+        use super::*;
+
+        #[test]
+        fn present() {
+            let value = decode(b"d3:cati1ee").unwrap();
+            assert_eq!(
+                value.dict_get_opt("cat").unwrap(),
+                Some(&BEncodedType::Integer(1))
+            );
+        }
+
+        #[test]
+        fn absent() {
+            let value = decode(b"d3:cati1ee").unwrap();
+            assert_eq!(value.dict_get_opt("dog").unwrap(), None);
+        }
+
+        #[test]
+        fn not_a_dictionary() {
+            assert!(BEncodedType::Integer(1).dict_get_opt("cat").is_err());
+        }
+    }
+
+    mod as_dict {
+        use super::*;
+
+        #[test]
+        fn several_lookups() {
+            let value = decode(b"d3:cati1e3:dogi2ee").unwrap();
+            let dict = value.as_dict().unwrap();
+            assert_eq!(dict_lookup(dict, "cat"), Some(&BEncodedType::Integer(1)));
+            assert_eq!(dict_lookup(dict, "dog"), Some(&BEncodedType::Integer(2)));
+            assert_eq!(dict_lookup(dict, "eel"), None);
+        }
+
+        #[test]
+        fn not_a_dictionary() {
+            let err = BEncodedType::Integer(1).as_dict().unwrap_err();
+            assert_eq!(err.to_string(), "Type is Integer, not a dictionary");
+        }
+    }
+
+    mod redacted {
+        use super::*;
+
+        #[test]
+        fn replaces_piece_hashes() {
+            let mut buf = b"d8:announce1:a4:infod6:lengthi8e4:name3:foo".to_vec();
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces40:");
+            buf.extend_from_slice(&[0xAB; 40]);
+            buf.extend_from_slice(b"e12:piece layersd32:");
+            buf.extend_from_slice(&[0xCD; 32]);
+            buf.extend_from_slice(b"64:");
+            buf.extend_from_slice(&[0xEF; 64]);
+            buf.extend_from_slice(b"ee");
+            let redacted = encode(&decode(&buf).unwrap().redacted().as_borrowed());
+            assert_eq!(
+                redacted,
+                b"d8:announce1:a4:infod6:lengthi8e4:name3:foo12:piece lengthi4e\
+                  6:pieces19:<redacted 2 pieces>e12:piece layers19:<redacted 2 pieces>e"
+                    .to_vec()
+            );
+            assert!(!redacted.windows(4).any(|x| x == [0xAB; 4]));
+        }
+
+        #[test]
+        fn leaves_other_values_alone() {
+            let value = decode(b"d4:infod4:name3:fooe6:pieces3:abce").unwrap();
+            assert_eq!(value.redacted(), OwnedBEncoded::from(&value));
+        }
+    }
+
+    mod map_strings {
+        use super::*;
+
+        #[test]
+        fn uppercases_values_not_keys() {
+            let value = decode(b"d3:abc3:xyz4:listl2:hii7eee").unwrap();
+            let mapped = value.map_strings(|x| x.to_ascii_uppercase());
+            assert_eq!(
+                encode(&mapped.as_borrowed()),
+                b"d3:abc3:XYZ4:listl2:HIi7eee".to_vec()
+            );
+        }
+    }
+
+    mod pointer {
+        use super::*;
+
+        #[test]
+        fn deep() {
+            let value = decode(b"d4:infod5:filesld6:lengthi5eed6:lengthi7eeeee").unwrap();
+            assert_eq!(
+                value.pointer("/info/files/1/length"),
+                Some(&BEncodedType::Integer(7))
+            );
+            assert_eq!(value.pointer(""), Some(&value));
+        }
+
+        #[test]
+        fn index_out_of_range() {
+            let value = decode(b"d1:lli1eee").unwrap();
+            assert_eq!(value.pointer("/l/1"), None);
+            assert_eq!(value.pointer("/l/x"), None);
+        }
+
+        #[test]
+        fn missing_key() {
+            let value = decode(b"d4:infod4:name3:fooee").unwrap();
+            assert_eq!(value.pointer("/info/length"), None);
+            assert_eq!(value.pointer("/info/name/x"), None);
+            assert_eq!(value.pointer("info"), None);
+        }
+
+        #[test]
+        fn numeric_and_escaped_keys() {
+            let value = decode(b"d1:0i1e3:a/bi2ee").unwrap();
+            assert_eq!(value.pointer("/0"), Some(&BEncodedType::Integer(1)));
+            assert_eq!(value.pointer("/a~1b"), Some(&BEncodedType::Integer(2)));
+        }
+    }
+
+    mod stats {
+        use super::*;
+
+        #[test]
+        fn nested_tree() {
+            let value = decode(b"d1:ali1eli2eee3:bcdd2:de3:fooee").unwrap();
+            assert_eq!(
+                value.stats(),
+                TreeStats {
+                    nodes: 7,
+                    max_depth: 3,
+                    dict_count: 2,
+                    list_count: 2,
+                    total_string_bytes: 9,
+                }
+            );
+        }
+
+        #[test]
+        fn depth_matches_decode_limits() {
+            let buf = b"llleee";
+            let depth = decode(buf).unwrap().stats().max_depth;
+            let limits = DecodeLimits::default().with_max_depth(depth);
+            assert!(decode_with(buf, &limits).is_ok());
+            let limits = limits.with_max_depth(depth - 1);
+            assert_eq!(decode_with(buf, &limits), Err(BencodeError::DepthExceeded));
+        }
+
+        #[test]
+        fn scalar() {
+            let stats = BEncodedType::from(5).stats();
+            assert_eq!((stats.nodes, stats.max_depth), (1, 0));
+        }
+    }
+
+    mod bencode_key_cmp {
+        use super::*;
+
+        #[test]
+        fn prefix_sorts_first() {
+            assert_eq!(bencode_key_cmp(b"a", b"aa"), Ordering::Less);
+            assert_eq!(bencode_key_cmp(b"aa", b"b"), Ordering::Less);
+            assert_eq!(bencode_key_cmp(b"", b"a"), Ordering::Less);
+            assert_eq!(bencode_key_cmp(b"ab", b"ab"), Ordering::Equal);
+        }
+
+        #[test]
+        fn bytes_are_unsigned() {
+            assert_eq!(bencode_key_cmp(b"\x80", b"z"), Ordering::Greater);
+            assert_eq!(bencode_key_cmp(b"\xff", b"\x7f"), Ordering::Greater);
+        }
+
+        #[test]
+        fn uppercase_before_lowercase() {
+            assert_eq!(bencode_key_cmp(b"Z", b"a"), Ordering::Less);
+        }
+
+        #[test]
+        fn encoder_uses_it() {
+            let value = BEncodedType::dict(vec![
+                ("\u{e9}", BEncodedType::from(1)),
+                ("z", BEncodedType::from(2)),
+                ("Z", BEncodedType::from(3)),
+            ]);
+            assert_eq!(encode(&value), "d1:Zi3e1:zi2e2:\u{e9}i1ee".as_bytes());
+        }
+    }
+
+    mod canonicalize {
+        use super::*;
+
+        #[test]
+        fn sorts_and_dedups_nested_dicts() {
+            let inner = BEncodedType::Dictionary(DictRepr::from(vec![
+                (&b"b"[..], BEncodedType::from(1)),
+                (b"a", BEncodedType::from(2)),
+                (b"b", BEncodedType::from(3)),
+            ]));
+            let mut value = BEncodedType::Dictionary(DictRepr::from(vec![
+                (&b"z"[..], BEncodedType::list([inner])),
+                (b"y", BEncodedType::from(4)),
+                (b"y", BEncodedType::from(5)),
+            ]));
+            value.canonicalize();
+            assert_eq!(encode(&value), b"d1:yi5e1:zld1:ai2e1:bi3eeee");
+        }
+
+        #[test]
+        fn lenient_input() {
+            let (mut value, _) = decode_lenient(b"d1:bi1e1:ai2e1:bi3ee").unwrap();
+            value.canonicalize();
+            assert_eq!(encode(&value), b"d1:ai2e1:bi3ee");
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod to_json {
+        use super::*;
+        use serde_json::{json, Value};
+
+        /// The inverse of `to_json`, to check nothing was lost.
+        fn from_json(value: &Value) -> OwnedBEncoded {
+            match value {
+                Value::String(text) => OwnedBEncoded::String(text.as_bytes().to_vec()),
+                Value::Number(x) => OwnedBEncoded::Integer(x.as_i64().unwrap()),
+                Value::Array(items) => OwnedBEncoded::List(items.iter().map(from_json).collect()),
+                Value::Object(object) => match object.get("__bytes_hex__") {
+                    Some(Value::String(hex)) if object.len() == 1 => OwnedBEncoded::String(
+                        (0..hex.len())
+                            .step_by(2)
+                            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                            .collect(),
+                    ),
+                    _ => OwnedBEncoded::Dictionary(
+                        object
+                            .iter()
+                            .map(|(key, value)| (key.as_bytes().to_vec(), from_json(value)))
+                            .collect(),
+                    ),
+                },
+                _ => panic!("to_json never produces {}", value),
+            }
+        }
+
+        #[test]
+        fn mapping() {
+            let value = decode(b"d3:inti-3e4:listl1:ae4:text2:hi3:raw2:\xff\x00e").unwrap();
+            assert_eq!(
+                value.to_json(),
+                json!({
+                    "int": -3,
+                    "list": ["a"],
+                    "text": "hi",
+                    "raw": {"__bytes_hex__": "ff00"},
+                })
+            );
+        }
+
+        #[test]
+        fn round_trips_a_torrent() {
+            let mut buf =
+                b"d8:announce17:http://t/announce4:infod5:filesld6:lengthi5e4:pathl1:a1:beee"
+                    .to_vec();
+            buf.extend_from_slice(b"4:name3:foo12:piece lengthi16384e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let value = decode(&buf).unwrap();
+            assert_eq!(from_json(&value.to_json()), OwnedBEncoded::from(&value));
         }
     }
 
-    pub fn as_str(&self) -> Result<&'a str> {
-        if let BEncodedType::String(ref x) = self {
-            str::from_utf8(x).or_else(|e| Err(anyhow!("UTF-8 decoding error: {:?}", e)))
-        } else {
-            Err(anyhow!("Type is {}, not a string", self.type_str()))
+    mod get_path {
+        use super::*;
+
+        const TORRENT: &[u8] = b"d4:infod6:lengthi4e4:name3:fooee";
+
+        #[test]
+        fn nested_str() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(value.get_str(&["info", "name"]).unwrap(), "foo");
+            assert_eq!(value.get_bytes(&["info", "name"]).unwrap(), b"foo");
+            assert_eq!(value.get_i64(&["info", "length"]).unwrap(), 4);
+        }
+
+        #[test]
+        fn wrong_type() {
+            let value = decode(TORRENT).unwrap();
+            let err = value.get_str(&["info", "length"]).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "info/length: Type is Integer, not a string"
+            );
+        }
+
+        #[test]
+        fn missing_segment() {
+            let value = decode(TORRENT).unwrap();
+            let err = value.get_i64(&["info", "files", "length"]).unwrap_err();
+            assert_eq!(err.to_string(), "info/files: Key not found");
         }
     }
 
-    pub fn as_str_bytes(&self) -> Result<&'a [u8]> {
-        if let BEncodedType::String(ref x) = self {
-            Ok(x)
-        } else {
-            Err(anyhow!("Type is {}, not a string", self.type_str()))
+    mod parse_one {
+        use super::*;
+
+        #[test]
+        fn returns_trailing_bytes() {
+            let buf = b"i5e\x00\xff\x10";
+            let (value, rest) = parse_one(buf).unwrap();
+            assert_eq!(value, BEncodedType::Integer(5));
+            assert_eq!(rest, b"\x00\xff\x10");
+        }
+
+        #[test]
+        fn consumes_whole_string() {
+            let (value, rest) = parse_one(b"3:foo").unwrap();
+            assert_eq!(value, BEncodedType::String(b"foo"));
+            assert!(rest.is_empty());
+        }
+
+        #[test]
+        fn fails_on_malformed_prefix() {
+            assert!(parse_one(b"x5e").is_err());
         }
     }
 
-    pub fn dict_keys(&self) -> Result<Vec<&'a str>> {
-        // if !matches(self, &BEncodedType::Dictionary) {
-        // }
-        if let BEncodedType::Dictionary(ref x) = self {
-            x.into_iter()
-                .map(|(&key, _value)| str::from_utf8(key).or(Err(anyhow!("UTF-8 decoding error"))))
-                .collect()
-        } else {
-            return Err(anyhow!("Not a dictionary"));
+    mod decode_all {
+        use super::*;
+
+        #[test]
+        fn concatenated_dicts() {
+            let values = decode_all(b"d1:ai1eed1:bi2ee").unwrap();
+            assert_eq!(
+                values,
+                vec![decode(b"d1:ai1ee").unwrap(), decode(b"d1:bi2ee").unwrap()]
+            );
+        }
+
+        #[test]
+        fn empty() {
+            assert!(decode_all(b"").unwrap().is_empty());
+        }
+
+        #[test]
+        fn trailing_garbage() {
+            assert_eq!(
+                decode_all(b"d1:ai1eex"),
+                Err(BencodeError::Malformed { offset: 8 })
+            );
+        }
+
+        #[test]
+        fn truncated_last_value() {
+            assert!(decode_all(b"d1:ai1eed1:b").is_err());
         }
     }
 
-    pub fn dict_get(&self, key: &str) -> Result<&BEncodedType<'a>> {
-        if let BEncodedType::Dictionary(ref x) = self {
-            x.get(key.as_bytes())
-                .ok_or_else(|| anyhow!("Key not found"))
-        } else {
-            Err(anyhow!("Not a dictionary"))
+    mod decode_partial {
+        use super::*;
+
+        #[test]
+        fn truncated_dict() {
+            let (value, err) = decode_partial(b"d4:infod6:lengthi4ee");
+            assert_eq!(value, None);
+            assert_eq!(err, Some(BencodeError::Malformed { offset: 20 }));
+        }
+
+        #[test]
+        fn complete() {
+            let (value, err) = decode_partial(b"d1:ai1ee");
+            assert_eq!(value, Some(decode(b"d1:ai1ee").unwrap()));
+            assert_eq!(err, None);
+        }
+
+        #[test]
+        fn keeps_first_of_concatenated_values() {
+            let (value, err) = decode_partial(b"d1:ai1eed1:b");
+            assert_eq!(value, Some(decode(b"d1:ai1ee").unwrap()));
+            assert_eq!(err, Some(BencodeError::TrailingData { offset: 8 }));
+        }
+
+        #[test]
+        fn empty() {
+            assert_eq!(
+                decode_partial(b""),
+                (None, Some(BencodeError::UnexpectedEof))
+            );
         }
     }
-}
 
-impl Debug for BEncodedType<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self {
-            &BEncodedType::String(ref x) => {
-                let parsed = std::str::from_utf8(x)
-                    .map(|y| y.to_owned())
-                    .unwrap_or_else(|_e| {
-                        x.into_iter()
-                            .map(|y| format!("{:02X?}", y))
-                            .collect::<String>()
-                    });
-                f.write_str(&parsed)
+    mod decode_prefix {
+        use super::*;
+
+        #[test]
+        fn reports_consumed_length() {
+            let dict = b"d1:ti1ee";
+            let mut buf = dict.to_vec();
+            buf.extend_from_slice(&[0xAA; 12]);
+            let (value, consumed) = decode_prefix(&buf).unwrap();
+            assert_eq!(value.dict_get("t").unwrap(), &BEncodedType::Integer(1));
+            assert_eq!(consumed, dict.len());
+            assert_eq!(&buf[consumed..], &[0xAA; 12]);
+        }
+
+        #[test]
+        fn fails_on_malformed_prefix() {
+            assert!(decode_prefix(b"d1:te").is_err());
+        }
+    }
+
+    mod raw_dict_entries {
+        use super::*;
+
+        #[test]
+        fn values_are_verbatim() {
+            let buf = b"d1:ai1e1:bl1:xe1:cd1:yi2eee";
+            let entries = raw_dict_entries(buf).unwrap();
+            let expected: Vec<(&[u8], &[u8])> =
+                vec![(b"a", b"i1e"), (b"b", b"l1:xe"), (b"c", b"d1:yi2ee")];
+            assert_eq!(entries, expected);
+        }
+
+        #[test]
+        fn empty_dictionary() {
+            assert!(raw_dict_entries(b"de").unwrap().is_empty());
+        }
+
+        #[test]
+        fn fails_on_non_dictionary() {
+            assert!(raw_dict_entries(b"li1ee").is_err());
+            assert!(raw_dict_entries(b"").is_err());
+            assert!(raw_dict_entries(b"d1:a").is_err());
+        }
+    }
+
+    mod encode {
+        use super::*;
+
+        #[test]
+        fn scalars() {
+            assert_eq!(encode(&BEncodedType::Integer(-42)), b"i-42e");
+            assert_eq!(encode(&BEncodedType::Integer(0)), b"i0e");
+            assert_eq!(encode(&BEncodedType::String(b"foo")), b"3:foo");
+            assert_eq!(encode(&BEncodedType::String(b"")), b"0:");
+        }
+
+        #[test]
+        fn sorts_dictionary_keys() {
+            let value = decode(b"d1:bi2e1:ai1e1:clee").unwrap();
+            assert_eq!(encode(&value), b"d1:ai1e1:bi2e1:clee");
+        }
+
+        #[test]
+        fn drops_repeated_keys() {
+            let value = decode(b"d1:ai1e1:ai2ee").unwrap();
+            assert_eq!(encode(&value), b"d1:ai2ee");
+            assert_eq!(value.encoded_len(), 8);
+        }
+
+        #[test]
+        fn round_trips() {
+            let buf: &[u8] = b"d4:infod6:lengthi12e4:name3:fooe4:listli1e2:abdeee";
+            assert_eq!(encode(&decode(buf).unwrap()), buf);
+        }
+    }
+
+    mod encode_with {
+        use super::*;
+
+        const OUT_OF_ORDER: &[u8] = b"d1:bi2e1:ai1e1:bd1:yi1e1:xi2eee";
+
+        #[test]
+        fn canonical() {
+            let (value, _) = decode_lenient(OUT_OF_ORDER).unwrap();
+            let options = EncodeOptions::default();
+            assert!(options.canonical);
+            assert_eq!(
+                encode_with(&value, &options),
+                b"d1:ai1e1:bd1:xi2e1:yi1eee".to_vec()
+            );
+            assert_eq!(encode(&value), encode_with(&value, &options));
+        }
+
+        #[test]
+        fn preserves_parsed_order() {
+            let (value, _) = decode_lenient(OUT_OF_ORDER).unwrap();
+            let options = EncodeOptions { canonical: false };
+            assert_eq!(encode_with(&value, &options), OUT_OF_ORDER.to_vec());
+        }
+
+        #[test]
+        fn preserves_hand_built_order() {
+            let value = BEncodedType::Dictionary(DictRepr::from(vec![
+                (&b"b"[..], BEncodedType::Integer(2)),
+                (b"a", BEncodedType::Integer(1)),
+            ]));
+            let options = EncodeOptions { canonical: false };
+            assert_eq!(encode_with(&value, &options), b"d1:bi2e1:ai1ee".to_vec());
+        }
+    }
+
+    mod encoded_len {
+        use super::*;
+
+        #[test]
+        fn matches_encode() {
+            let long = [b'x'; 100];
+            let values = [
+                BEncodedType::Integer(0),
+                BEncodedType::Integer(9),
+                BEncodedType::Integer(-10),
+                BEncodedType::Integer(i64::MIN),
+                BEncodedType::Integer(i64::MAX),
+                BEncodedType::String(b""),
+                BEncodedType::String(&long[..9]),
+                BEncodedType::String(&long[..10]),
+                BEncodedType::String(&long),
+                BEncodedType::list([]),
+                decode(b"d4:infod6:lengthi12e4:name3:fooe4:listli1e2:abdeee").unwrap(),
+            ];
+            for value in &values {
+                assert_eq!(value.encoded_len(), encode(value).len(), "{:?}", value);
             }
-            &BEncodedType::Integer(ref x) => f.write_str(&format!("{}", x)),
-            &BEncodedType::List(ref x) => f.debug_list().entries(x).finish(),
-            &BEncodedType::Dictionary(ref x) => {
-                let mut fmt_mapper = f.debug_map();
-                for pair in x.iter() {
-                    let key = String::from_utf8_lossy(*pair.0);
-                    fmt_mapper.key(&key);
-                    fmt_mapper.value(pair.1);
-                }
-                fmt_mapper.finish()
+        }
+
+        #[test]
+        fn repeated_and_unsorted_keys() {
+            for input in [&b"d1:ai1e1:ai22ee"[..], b"d1:bi1e1:ai2e1:bi333ee"] {
+                let (value, _) = decode_lenient(input).unwrap();
+                assert_eq!(value.encoded_len(), encode(&value).len(), "{:?}", value);
             }
         }
     }
-}
 
-fn string_from_digit(input: &[u8]) -> Result<&str> {
-    std::str::from_utf8(input).map_err(|e| anyhow!("Error during bytes to string parsing: {:?}", e))
-}
+    #[cfg(feature = "std")]
+    mod encode_to {
+        use super::*;
 
-fn from_digit<T: std::str::FromStr>(input: &[u8]) -> Result<T>
-where
-    <T as std::str::FromStr>::Err: std::fmt::Debug,
-{
-    let buf = string_from_digit(input)?;
-    buf.parse::<T>()
-        .map_err(|e| anyhow!("Error during string to digit parsing: {:?}", e))
-}
+        #[test]
+        fn matches_encode() {
+            let value = decode(b"d1:bli1e3:fooe1:ad1:zi-3e1:y0:ee").unwrap();
+            let mut buf = Vec::new();
+            encode_to(&value, &mut buf).unwrap();
+            assert_eq!(buf, encode(&value));
+        }
 
-fn non_zero_signed_digit1(input: &[u8]) -> nom::IResult<&[u8], i64> {
-    let negative = opt(value(-1, tag(b"-")));
-    let non_zero_peek = peek(one_of(b"123456789" as &[u8]));
-    let non_zero_digit1 = map_res(preceded(non_zero_peek, digit1), from_digit::<i64>);
-    let (rest, (sign, digit)) = tuple((negative, non_zero_digit1))(input)?;
-    let signed_digit = sign.unwrap_or(1) * digit;
-    Ok((rest, signed_digit))
-}
+        #[test]
+        fn reports_write_errors() {
+            let mut buf = [0u8; 4];
+            let mut w = &mut buf[..];
+            assert!(encode_to(&BEncodedType::String(b"foobar"), &mut w).is_err());
+        }
+    }
 
-fn non_zero_padded_digit(input: &[u8]) -> nom::IResult<&[u8], i64> {
-    let zero = value(0, verify(digit1, |x: &[u8]| x == b"0"));
-    alt((zero, non_zero_signed_digit1))(input)
-}
+    mod builders {
+        use super::*;
 
-fn parse_primitive(input: &[u8]) -> nom::IResult<&[u8], BEncodedType> {
-    let str_parser = map(parse_str, |x: &[u8]| BEncodedType::String(x));
-    let int_parser = map(parse_int, |x: i64| BEncodedType::Integer(x));
-    let list_parser = map(parse_list, |x: Vec<BEncodedType>| BEncodedType::List(x));
-    let dict_parser = map(parse_dictionary, |x: HashMap<&[u8], BEncodedType>| {
-        BEncodedType::Dictionary(x)
-    });
-    alt((str_parser, int_parser, list_parser, dict_parser))(input)
-}
+        #[test]
+        fn builds_and_encodes_dict() {
+            let value = BEncodedType::dict([
+                ("name", BEncodedType::string("foo")),
+                ("length", BEncodedType::from(42)),
+                (
+                    "path",
+                    BEncodedType::list([BEncodedType::string("a"), BEncodedType::bytes(b"\xff")]),
+                ),
+            ]);
+            assert_eq!(
+                encode(&value),
+                b"d6:lengthi42e4:name3:foo4:pathl1:a1:\xffee"
+            );
+            assert_eq!(value.dict_get("name").unwrap().as_str().unwrap(), "foo");
+        }
 
-fn parse_str(input: &[u8]) -> nom::IResult<&[u8], &[u8]> {
-    let (suffix, (len, _)) = tuple((map_res(digit1, from_digit::<usize>), tag(":")))(input)?;
+        #[test]
+        fn dict_accepts_byte_keys() {
+            let key: &[u8] = b"\x01";
+            let value = BEncodedType::dict([(key, BEncodedType::from(1))]);
+            assert_eq!(encode(&value), b"d1:\x01i1ee");
+        }
 
-    take(len)(suffix)
-}
+        #[test]
+        fn collects_list() {
+            let value: BEncodedType = (1..=3).map(BEncodedType::from).collect();
+            assert_eq!(encode(&value), b"li1ei2ei3ee");
+        }
 
-fn parse_int(input: &[u8]) -> nom::IResult<&[u8], i64> {
-    let prefix = tag("i");
-    let suffix = tag("e");
-    terminated(preceded(prefix, non_zero_padded_digit), suffix)(input)
-}
+        #[test]
+        fn collects_sorted_dict() {
+            let entries: [(&[u8], _); 2] = [(b"b", 1), (b"a", 2)];
+            let value: BEncodedType = entries
+                .iter()
+                .map(|&(key, x)| (key, BEncodedType::from(x)))
+                .collect();
+            assert_eq!(encode(&value), b"d1:ai2e1:bi1ee");
+        }
 
-fn parse_list(input: &[u8]) -> nom::IResult<&[u8], Vec<BEncodedType>> {
-    let prefix = tag("l");
-    let suffix = tag("e");
-    let items = many0(parse_primitive);
-    terminated(preceded(prefix, items), suffix)(input)
-}
+        #[test]
+        fn duplicate_keys_keep_the_last() {
+            let entries: [(&[u8], _); 3] = [(b"a", 1), (b"b", 2), (b"a", 3)];
+            let value: BEncodedType = entries
+                .iter()
+                .map(|&(key, x)| (key, BEncodedType::from(x)))
+                .collect();
+            assert_eq!(encode(&value), b"d1:ai3e1:bi2ee");
+        }
+    }
+
+    mod owned {
+        use super::*;
 
-fn parse_dictionary(input: &[u8]) -> nom::IResult<&[u8], HashMap<&[u8], BEncodedType>> {
-    let prefix = tag("d");
-    let suffix = tag("e");
-    let kv = pair(parse_str, parse_primitive);
-    let items = many0(kv);
-    let (rest, pairs) = terminated(preceded(prefix, items), suffix)(input)?;
+        #[test]
+        fn round_trips_through_borrowed() {
+            let value = decode(b"d1:ali1e1:xe1:bd1:ci-3eee").unwrap();
+            let owned = OwnedBEncoded::from(&value);
+            assert_eq!(owned.as_borrowed(), value);
+        }
 
-    // TODO: dictionaries are supposed to come in with sorted keys.  Verify that.
-    let res = pairs.into_iter().collect();
-    return Ok((rest, res));
-}
+        #[test]
+        fn clones_are_independent() {
+            let value = decode(b"d4:infod6:lengthi4eee").unwrap();
+            let cloned = value.clone();
+            assert_eq!(cloned, value);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use nom::error::ErrorKind;
+            let mut owned = OwnedBEncoded::from(&cloned);
+            let copy = owned.clone();
+            if let OwnedBEncoded::Dictionary(x) = &mut owned {
+                x.insert(b"announce".to_vec(), OwnedBEncoded::Integer(1));
+            }
+            assert_eq!(copy.as_borrowed(), value);
+            assert_ne!(owned.as_borrowed(), value);
+        }
+    }
+
+    mod display {
+        use super::*;
+
+        #[test]
+        fn scalars() {
+            assert_eq!(BEncodedType::Integer(-3).to_string(), "-3");
+            assert_eq!(BEncodedType::String(b"foo").to_string(), "foo");
+            assert_eq!(
+                BEncodedType::String(b"\xff\x00\x01\x02\x03\x04\x05\x06\x07").to_string(),
+                "<9 bytes: ff00010203040506...>"
+            );
+        }
 
-    fn nom_error<T>(remaining: &[u8], kind: nom::error::ErrorKind) -> nom::IResult<&[u8], T> {
-        Err(nom::Err::Error(nom::error::Error::new(remaining, kind)))
+        #[test]
+        fn nested() {
+            let buf = b"d3:fool1:a2:\xff\xfeld2:abi-1eeee3:bari1e5:emptylee";
+            let expected = "\
+{
+  bar: 1
+  empty: []
+  foo: [
+    a
+    <2 bytes: fffe>
+    [
+      {
+        ab: -1
+      }
+    ]
+  ]
+}";
+            assert_eq!(decode(buf).unwrap().to_string(), expected);
+        }
     }
 
-    fn nom_failure<T>(remaining: &[u8], kind: nom::error::ErrorKind) -> nom::IResult<&[u8], T> {
-        Err(nom::Err::Failure(nom::error::Error::new(remaining, kind)))
+    mod equality {
+        use super::*;
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(value: &BEncodedType) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        #[test]
+        fn dictionaries_ignore_key_order() {
+            let a = decode(b"d1:ai1e1:bl1:xe1:cd1:yi2e1:zi3eee").unwrap();
+            let b = decode(b"d1:cd1:zi3e1:yi2ee1:bl1:xe1:ai1ee").unwrap();
+            assert_eq!(a, b);
+            assert_eq!(hash_of(&a), hash_of(&b));
+        }
+
+        #[test]
+        fn differing_values_are_unequal() {
+            let a = decode(b"d1:ai1e1:bi2ee").unwrap();
+            let b = decode(b"d1:ai1e1:bi3ee").unwrap();
+            assert_ne!(a, b);
+        }
+
+        #[test]
+        fn differing_keys_are_unequal() {
+            let a = decode(b"d1:ai1ee").unwrap();
+            let b = decode(b"d1:bi1ee").unwrap();
+            assert_ne!(a, b);
+        }
+
+        #[test]
+        fn lists_respect_order() {
+            let a = decode(b"li1ei2ee").unwrap();
+            let b = decode(b"li2ei1ee").unwrap();
+            assert_ne!(a, b);
+        }
+
+        #[test]
+        fn types_must_match() {
+            assert_ne!(BEncodedType::String(b"1"), BEncodedType::Integer(1));
+            assert_ne!(
+                BEncodedType::List(vec![]),
+                BEncodedType::Dictionary(DictRepr::default())
+            );
+        }
     }
 
     mod parse_primitive {
@@ -193,14 +2471,20 @@ mod tests {
         fn string() {
             let buf = b"6:foobar";
             let expected: (&[u8], BEncodedType) = (b"", BEncodedType::String(b"foobar"));
-            assert_eq!(parse_primitive(buf), Ok(expected));
+            assert_eq!(
+                parse_primitive(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            );
         }
 
         #[test]
         fn integer() {
             let buf = b"i13e";
             let expected: (&[u8], BEncodedType) = (b"", BEncodedType::Integer(13));
-            assert_eq!(parse_primitive(buf), Ok(expected));
+            assert_eq!(
+                parse_primitive(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            );
         }
 
         #[test]
@@ -208,17 +2492,25 @@ mod tests {
             let buf = b"li14ee";
             let expected: (&[u8], BEncodedType) =
                 (b"", BEncodedType::List(vec![BEncodedType::Integer(14)]));
-            assert_eq!(parse_primitive(buf), Ok(expected))
+            assert_eq!(
+                parse_primitive(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            )
         }
 
         #[test]
         fn dictionary() {
             let buf = b"d5:carvei55e7:deutschi4ee";
-            let mut expected: HashMap<&[u8], _> = HashMap::with_capacity(2);
-            expected.insert(b"deutsch", BEncodedType::Integer(4));
-            expected.insert(b"carve", BEncodedType::Integer(55));
-            let expected_wrapper: (&[u8], BEncodedType) = (b"", BEncodedType::Dictionary(expected));
-            assert_eq!(parse_primitive(buf), Ok(expected_wrapper));
+            let expected: Vec<(&[u8], _)> = vec![
+                (b"carve", BEncodedType::Integer(55)),
+                (b"deutsch", BEncodedType::Integer(4)),
+            ];
+            let expected_wrapper: (&[u8], BEncodedType) =
+                (b"", BEncodedType::Dictionary(expected.into()));
+            assert_eq!(
+                parse_primitive(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            );
         }
     }
 
@@ -228,50 +2520,59 @@ mod tests {
         #[test]
         fn single_entry() {
             let buf = b"d6:foobari9ee";
-            let mut expected: HashMap<&[u8], _> = HashMap::with_capacity(1);
-            expected.insert(b"foobar", BEncodedType::Integer(9));
-            let expected_wrapper: (&[u8], _) = (b"", expected);
-            assert_eq!(parse_dictionary(buf), Ok(expected_wrapper))
+            let expected: Vec<(&[u8], _)> = vec![(b"foobar", BEncodedType::Integer(9))];
+            let expected_wrapper: (&[u8], _) = (b"", DictRepr::from(expected));
+            assert_eq!(
+                parse_dictionary(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            )
         }
 
         #[test]
         fn multiple_entries() {
             let buf = b"d3:cat3:doge";
-            let mut expected: HashMap<&[u8], _> = HashMap::with_capacity(1);
-            expected.insert(b"cat", BEncodedType::String(b"dog"));
-            let expected_wrapper: (&[u8], _) = (b"", expected);
-            assert_eq!(parse_dictionary(buf), Ok(expected_wrapper));
+            let expected: Vec<(&[u8], _)> = vec![(b"cat", BEncodedType::String(b"dog"))];
+            let expected_wrapper: (&[u8], _) = (b"", DictRepr::from(expected));
+            assert_eq!(
+                parse_dictionary(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            );
         }
 
         #[test]
         fn zero_entries() {
             let buf = b"de";
-            let expected = HashMap::new();
-            let expected_wrapper: (&[u8], _) = (b"", expected);
-            assert_eq!(parse_dictionary(buf), Ok(expected_wrapper));
+            let expected = Vec::new();
+            let expected_wrapper: (&[u8], _) = (b"", DictRepr::from(expected));
+            assert_eq!(
+                parse_dictionary(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            );
         }
 
         #[test]
         fn list_value() {
             let buf = b"d5:hoshil5:uuchi6:jigokuee";
-            let mut expected: HashMap<&[u8], _> = HashMap::with_capacity(1);
-            expected.insert(
+            let expected: Vec<(&[u8], _)> = vec![(
                 b"hoshi",
                 BEncodedType::List(vec![
                     BEncodedType::String(b"uuchi"),
                     BEncodedType::String(b"jigoku"),
                 ]),
-            );
-            let expected_wrapper: (&[u8], _) = (b"", expected);
-            assert_eq!(parse_dictionary(buf), Ok(expected_wrapper))
+            )];
+            let expected_wrapper: (&[u8], _) = (b"", DictRepr::from(expected));
+            assert_eq!(
+                parse_dictionary(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            )
         }
 
         #[test]
         fn non_string_key() {
             let buf = b"di12ei99ee";
             assert_eq!(
-                parse_dictionary(buf),
-                nom_failure(b"i12ei99ee", ErrorKind::Tag)
+                parse_dictionary(buf, 0, &ParseState::new(DecodeLimits::default())),
+                syntax_failure(b"i12ei99ee", ErrorKind::Tag)
             );
         }
     }
@@ -286,14 +2587,20 @@ mod tests {
                 b"",
                 vec![BEncodedType::Integer(12), BEncodedType::Integer(-17)],
             );
-            assert_eq!(parse_list(buf), Ok(expected));
+            assert_eq!(
+                parse_list(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            );
         }
 
         #[test]
         fn empty_list() {
             let buf = b"le";
             let expected: (&[u8], _) = (b"", vec![]);
-            assert_eq!(parse_list(buf), Ok(expected));
+            assert_eq!(
+                parse_list(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            );
         }
 
         #[test]
@@ -303,7 +2610,10 @@ mod tests {
                 b"",
                 vec![BEncodedType::Integer(18), BEncodedType::String(b"hello")],
             );
-            assert_eq!(parse_list(buf), Ok(expected));
+            assert_eq!(
+                parse_list(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected)
+            );
         }
 
         #[test]
@@ -318,7 +2628,10 @@ mod tests {
                 BEncodedType::String(b"baz"),
             ];
             let expected_wrapper: (&[u8], _) = (b"", expected);
-            assert_eq!(parse_list(buf), Ok(expected_wrapper));
+            assert_eq!(
+                parse_list(buf, 0, &ParseState::new(DecodeLimits::default())),
+                Ok(expected_wrapper)
+            );
         }
     }
 
@@ -334,13 +2647,21 @@ mod tests {
         #[test]
         fn doesnt_parse_badly_identified_integer() {
             let buf: &[u8] = b"55e";
-            assert_eq!(parse_int(buf), nom_error(buf, ErrorKind::Tag));
+            assert_eq!(parse_int(buf), syntax_error(buf, ErrorKind::Tag));
         }
 
         #[test]
         fn doesnt_parse_zero_padded_integer() {
             let buf = b"i032e";
-            assert_eq!(parse_int(buf), nom_failure(b"032e", ErrorKind::OneOf));
+            assert_eq!(parse_int(buf), syntax_failure(b"032e", ErrorKind::OneOf));
+        }
+
+        #[test]
+        fn parses_extreme_integers() {
+            let expected: (&[u8], _) = (b"", i64::MIN);
+            assert_eq!(parse_int(b"i-9223372036854775808e"), Ok(expected));
+            let expected: (&[u8], _) = (b"", i64::MAX);
+            assert_eq!(parse_int(b"i9223372036854775807e"), Ok(expected));
         }
 
         #[test]
@@ -349,6 +2670,16 @@ mod tests {
             let expected: (&[u8], _) = (b"", -42);
             assert_eq!(parse_int(buf), Ok(expected));
         }
+
+        #[test]
+        fn rejects_plus_sign() {
+            assert_eq!(parse_int(b"i+5e"), syntax_failure(b"+5e", ErrorKind::OneOf));
+            assert_eq!(decode(b"i+5e"), Err(BencodeError::Malformed { offset: 1 }));
+            assert!(decode_lenient(b"i+5e").is_err());
+            assert!(decode_lenient(b"i+05e").is_err());
+            assert_eq!(decode(b"i5e"), Ok(BEncodedType::Integer(5)));
+            assert_eq!(decode(b"i-5e"), Ok(BEncodedType::Integer(-5)));
+        }
     }
 
     mod parse_str {
@@ -364,13 +2695,40 @@ mod tests {
         #[test]
         fn fails_on_non_string() {
             let buf = b"i32";
-            assert_eq!(parse_str(buf), nom_error(b"i32", ErrorKind::Digit));
+            assert_eq!(parse_str(buf), syntax_error(b"i32", ErrorKind::Digit));
         }
 
         #[test]
         fn fails_on_short_string() {
             let buf = b"23:foobar";
-            assert_eq!(parse_str(buf), nom_failure(b"foobar", ErrorKind::Eof));
+            assert_eq!(
+                parse_str(buf),
+                Err(limit_error(BencodeError::BadStringLength(23)))
+            );
+        }
+
+        #[test]
+        fn fails_cleanly_on_huge_length() {
+            assert_eq!(
+                decode(b"18446744073709551615:"),
+                Err(BencodeError::BadStringLength(18446744073709551615))
+            );
+            assert_eq!(
+                decode(b"l18446744073709551615:foo"),
+                Err(BencodeError::BadStringLength(18446744073709551615))
+            );
+        }
+
+        #[test]
+        fn fails_on_missing_colon() {
+            let buf = b"3foo";
+            assert_eq!(parse_str(buf), syntax_error(b"foo", ErrorKind::Tag));
+        }
+
+        #[test]
+        fn fails_on_overflowing_length() {
+            let buf = b"99999999999999999999999:foo";
+            assert_eq!(parse_str(buf), syntax_error(buf, ErrorKind::MapRes));
         }
     }
 }
@@ -0,0 +1,177 @@
+//! Structural comparison of two bencoded trees.
+
+use core::cmp::Ordering;
+
+use super::{bencode_key_cmp, BEncodedType};
+use crate::alloc_prelude::*;
+
+/// One difference between two trees.
+#[derive(Debug, PartialEq)]
+pub struct DiffEntry {
+    /// Where the difference is, as a JSON-Pointer-like path of dictionary
+    /// keys and list indices, e.g. `/info/files/0/length`.  The root is `""`.
+    pub path: String,
+    pub kind: DiffKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffKind {
+    /// The key or list item only exists in the first tree.
+    OnlyInA,
+    /// The key or list item only exists in the second tree.
+    OnlyInB,
+    /// Both trees hold a string or integer here, with different values.
+    Changed,
+    /// The trees hold values of different types here.
+    TypeMismatch { a: &'static str, b: &'static str },
+}
+
+/// Lists every difference between `a` and `b`, in path order.  Dictionary
+/// key order doesn't count as a difference.
+pub fn diff(a: &BEncodedType, b: &BEncodedType) -> Vec<DiffEntry> {
+    let mut entries = Vec::new();
+    diff_into(a, b, &mut String::new(), &mut entries);
+    entries
+}
+
+fn diff_into(a: &BEncodedType, b: &BEncodedType, path: &mut String, out: &mut Vec<DiffEntry>) {
+    match (a, b) {
+        (BEncodedType::String(x), BEncodedType::String(y)) if x != y => {
+            out.push(entry(path, DiffKind::Changed))
+        }
+        (BEncodedType::Integer(x), BEncodedType::Integer(y)) if x != y => {
+            out.push(entry(path, DiffKind::Changed))
+        }
+        (BEncodedType::List(x), BEncodedType::List(y)) => {
+            for i in 0..x.len().max(y.len()) {
+                with_segment(path, &i.to_string(), |path| match (x.get(i), y.get(i)) {
+                    (Some(x), Some(y)) => diff_into(x, y, path, out),
+                    (Some(_), None) => out.push(entry(path, DiffKind::OnlyInA)),
+                    (None, Some(_)) => out.push(entry(path, DiffKind::OnlyInB)),
+                    (None, None) => unreachable!(),
+                });
+            }
+        }
+        (BEncodedType::Dictionary(x), BEncodedType::Dictionary(y)) => {
+            let x: Vec<_> = x.iter_by_key().collect();
+            let y: Vec<_> = y.iter_by_key().collect();
+            let (mut i, mut j) = (0, 0);
+            while i < x.len() || j < y.len() {
+                let order = match (x.get(i), y.get(j)) {
+                    (Some(x), Some(y)) => bencode_key_cmp(x.0, y.0),
+                    (Some(_), None) => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let key = match order {
+                    Ordering::Greater => y[j].0,
+                    _ => x[i].0,
+                };
+                with_segment(path, &String::from_utf8_lossy(key), |path| match order {
+                    Ordering::Less => out.push(entry(path, DiffKind::OnlyInA)),
+                    Ordering::Greater => out.push(entry(path, DiffKind::OnlyInB)),
+                    Ordering::Equal => diff_into(&x[i].1, &y[j].1, path, out),
+                });
+                match order {
+                    Ordering::Less => i += 1,
+                    Ordering::Greater => j += 1,
+                    Ordering::Equal => {
+                        i += 1;
+                        j += 1;
+                    }
+                }
+            }
+        }
+        (BEncodedType::String(_), BEncodedType::String(_))
+        | (BEncodedType::Integer(_), BEncodedType::Integer(_)) => {}
+        _ => out.push(entry(
+            path,
+            DiffKind::TypeMismatch {
+                a: a.type_str(),
+                b: b.type_str(),
+            },
+        )),
+    }
+}
+
+fn entry(path: &str, kind: DiffKind) -> DiffEntry {
+    DiffEntry {
+        path: path.to_string(),
+        kind,
+    }
+}
+
+/// Runs `f` with `segment` appended to `path`, escaping `~` and `/` the way
+/// JSON Pointer does.
+fn with_segment(path: &mut String, segment: &str, f: impl FnOnce(&mut String)) {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    f(path);
+    path.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::decode;
+
+    mod diff {
+        use super::*;
+
+        #[test]
+        fn nested_value_changed() {
+            let a = decode(b"d4:infod6:lengthi5e4:name3:fooe4:spam3:egge").unwrap();
+            let b = decode(b"d4:infod6:lengthi6e4:name3:fooe4:spam3:egge").unwrap();
+            assert_eq!(
+                diff(&a, &b),
+                vec![DiffEntry {
+                    path: "/info/length".to_string(),
+                    kind: DiffKind::Changed,
+                }]
+            );
+        }
+
+        #[test]
+        fn identical() {
+            let a = decode(b"d1:ali1ei2ee1:b3:fooe").unwrap();
+            assert!(diff(&a, &a).is_empty());
+        }
+
+        #[test]
+        fn missing_keys_and_items() {
+            let a = decode(b"d1:ai1e1:lli1ei2eee").unwrap();
+            let b = decode(b"d1:bi1e1:lli1eee").unwrap();
+            assert_eq!(
+                diff(&a, &b),
+                vec![
+                    entry("/a", DiffKind::OnlyInA),
+                    entry("/b", DiffKind::OnlyInB),
+                    entry("/l/1", DiffKind::OnlyInA),
+                ]
+            );
+        }
+
+        #[test]
+        fn type_mismatch() {
+            let a = decode(b"d1:xi1ee").unwrap();
+            let b = decode(b"d1:xlee").unwrap();
+            assert_eq!(
+                diff(&a, &b),
+                vec![entry(
+                    "/x",
+                    DiffKind::TypeMismatch {
+                        a: "Integer",
+                        b: "List"
+                    }
+                )]
+            );
+        }
+
+        #[test]
+        fn escapes_keys() {
+            let a = decode(b"d3:a/bi1ee").unwrap();
+            let b = decode(b"d3:a/bi2ee").unwrap();
+            assert_eq!(diff(&a, &b)[0].path, "/a~1b");
+        }
+    }
+}
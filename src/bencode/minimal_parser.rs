@@ -0,0 +1,211 @@
+//! A hand-written recursive-descent parser for builds without nom.  It keeps
+//! the nom parser's interface and reports the same errors at the same
+//! positions, so both pass the same tests.
+
+use super::{
+    check_dict_order, limit_error, parse_int_with, BEncodedType, BencodeError, DictRepr, IResult,
+    ParseError, ParseState,
+};
+use crate::alloc_prelude::*;
+
+/// Mirrors `nom::Err`: an `Error` lets an enclosing parser try something
+/// else, a `Failure` aborts the whole parse.
+#[derive(Debug, PartialEq)]
+pub(super) enum Err<E> {
+    Error(E),
+    Failure(E),
+}
+
+/// Mirrors `nom::error::Error`: where parsing failed and what was expected.
+#[derive(Debug, PartialEq)]
+pub(super) struct Error<I> {
+    pub input: I,
+    pub code: ErrorKind,
+}
+
+impl<I> Error<I> {
+    pub fn new(input: I, code: ErrorKind) -> Self {
+        Error { input, code }
+    }
+}
+
+/// The subset of `nom::error::ErrorKind` this parser can report.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(super) enum ErrorKind {
+    Tag,
+    MapRes,
+    Digit,
+    OneOf,
+}
+
+pub(super) fn to_bencode_error(e: Err<ParseError<'_>>, input: &[u8]) -> BencodeError {
+    match e {
+        Err::Error(e) | Err::Failure(e) => e.into_bencode_error(input),
+    }
+}
+
+fn error<T>(input: &[u8], kind: ErrorKind) -> IResult<'_, T> {
+    Err(Err::Error(ParseError::Syntax(Error::new(input, kind))))
+}
+
+/// Turns a recoverable error into a failure, like nom's `cut`.
+fn cut<T>(result: IResult<'_, T>) -> IResult<'_, T> {
+    match result {
+        Err(Err::Error(e)) => Err(Err::Failure(e)),
+        other => other,
+    }
+}
+
+fn tag(input: &[u8], byte: u8) -> IResult<'_, ()> {
+    match input.split_first() {
+        Some((&first, rest)) if first == byte => Ok((rest, ())),
+        _ => error(input, ErrorKind::Tag),
+    }
+}
+
+/// Splits off the leading run of ASCII digits, which must not be empty.
+fn digit1(input: &[u8]) -> IResult<'_, &[u8]> {
+    let len = input.iter().take_while(|x| x.is_ascii_digit()).count();
+    if len == 0 {
+        return error(input, ErrorKind::Digit);
+    }
+    Ok((&input[len..], &input[..len]))
+}
+
+/// Parses an optional sign and ASCII digits, failing only on overflow.
+fn parse_number<T: core::str::FromStr>(digits: &[u8]) -> Option<T> {
+    core::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn non_zero_signed_digit1(input: &[u8]) -> IResult<'_, i64> {
+    let unsigned = match input.first() {
+        Some(b'-') => &input[1..],
+        _ => input,
+    };
+    match unsigned.first() {
+        Some(b'1'..=b'9') => {}
+        _ => return error(unsigned, ErrorKind::OneOf),
+    }
+    let (rest, _) = digit1(unsigned)?;
+    // Parse the sign along with the digits so i64::MIN doesn't overflow.
+    let signed = &input[..input.len() - rest.len()];
+    match parse_number(signed) {
+        Some(x) => Ok((rest, x)),
+        None => error(input, ErrorKind::MapRes),
+    }
+}
+
+fn non_zero_padded_digit(input: &[u8]) -> IResult<'_, i64> {
+    match digit1(input) {
+        Ok((rest, b"0")) => Ok((rest, 0)),
+        _ => non_zero_signed_digit1(input),
+    }
+}
+
+/// Parses one value of any type.  `depth` is how many lists and dictionaries
+/// enclose this value.
+pub(super) fn parse_primitive<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, BEncodedType<'a>> {
+    let (rest, parsed) = match input.first() {
+        Some(b'0'..=b'9') => match parse_limited_str(input, state) {
+            Ok((rest, x)) => (rest, BEncodedType::String(x)),
+            // Like nom's `alt`, a recoverable error falls through to the
+            // other alternatives, which all fail on the leading tag.
+            Err(Err::Error(_)) => return error(input, ErrorKind::Tag),
+            Err(e) => return Err(e),
+        },
+        Some(b'i') => {
+            let (rest, x) = parse_int_with(input, state)?;
+            (rest, BEncodedType::Integer(x))
+        }
+        Some(b'l') => {
+            let (rest, x) = parse_list(input, depth, state)?;
+            (rest, BEncodedType::List(x))
+        }
+        Some(b'd') => {
+            let (rest, x) = parse_dictionary(input, depth, state)?;
+            (rest, BEncodedType::Dictionary(x))
+        }
+        _ => return error(input, ErrorKind::Tag),
+    };
+    state.take_entry()?;
+    Ok((rest, parsed))
+}
+
+fn parse_limited_str<'a>(input: &'a [u8], state: &ParseState) -> IResult<'a, &'a [u8]> {
+    state.check_length_prefix(input)?;
+    let (rest, s) = parse_str(input)?;
+    state.check_string(s)?;
+    Ok((rest, s))
+}
+
+pub(super) fn parse_str(input: &[u8]) -> IResult<'_, &[u8]> {
+    let (rest, digits) = digit1(input)?;
+    let len = match parse_number::<usize>(digits) {
+        Some(len) => len,
+        None => return error(input, ErrorKind::MapRes),
+    };
+    let (suffix, _) = tag(rest, b':')?;
+
+    // Checked before slicing, so a huge declared length fails cleanly.
+    if suffix.len() < len {
+        return Err(limit_error(BencodeError::BadStringLength(len)));
+    }
+    Ok((&suffix[len..], &suffix[..len]))
+}
+
+pub(super) fn parse_int(input: &[u8]) -> IResult<'_, i64> {
+    let (rest, _) = tag(input, b'i')?;
+    let (rest, x) = cut(non_zero_padded_digit(rest))?;
+    let (rest, _) = cut(tag(rest, b'e'))?;
+    Ok((rest, x))
+}
+
+pub(super) fn parse_list<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, Vec<BEncodedType<'a>>> {
+    let (mut input, _) = tag(input, b'l')?;
+    let depth = state.descend(depth)?;
+    let mut items = Vec::new();
+    loop {
+        match parse_primitive(input, depth, state) {
+            Ok((rest, item)) => {
+                items.push(item);
+                input = rest;
+            }
+            Err(Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let (rest, _) = cut(tag(input, b'e'))?;
+    Ok((rest, items))
+}
+
+pub(super) fn parse_dictionary<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, DictRepr<'a>> {
+    let (mut body, _) = tag(input, b'd')?;
+    let depth = state.descend(depth)?;
+    let mut pairs = Vec::new();
+    loop {
+        let (value_start, key) = match parse_limited_str(body, state) {
+            Ok(parsed) => parsed,
+            Err(Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        };
+        let (rest, value) = cut(parse_primitive(value_start, depth, state))?;
+        pairs.push((key, value));
+        body = rest;
+    }
+    let (rest, _) = cut(tag(body, b'e'))?;
+    let dict = DictRepr::from(pairs);
+    check_dict_order(&dict, input, state);
+    Ok((rest, dict))
+}
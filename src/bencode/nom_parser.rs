@@ -0,0 +1,165 @@
+//! The default parser, built from nom combinators.
+
+use nom::error::FromExternalError;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{digit1, one_of},
+    combinator::{cut, map, map_res, opt, peek, recognize, value, verify},
+    multi::many0,
+    sequence::{pair, preceded, terminated, tuple},
+};
+
+pub(super) use nom::error::{Error, ErrorKind};
+pub(super) use nom::Err;
+
+use super::{
+    check_dict_order, limit_error, parse_int_with, BEncodedType, BencodeError, DictRepr, IResult,
+    ParseError, ParseState,
+};
+use crate::alloc_prelude::*;
+
+/// Parses ASCII digits, failing on overflow.  `map_res` only needs to know
+/// that it failed, not why.
+fn from_digit<T: core::str::FromStr>(input: &[u8]) -> Result<T, ()> {
+    core::str::from_utf8(input)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .ok_or(())
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        ParseError::Syntax(Error::new(input, kind))
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a, E> FromExternalError<&'a [u8], E> for ParseError<'a> {
+    fn from_external_error(input: &'a [u8], kind: ErrorKind, _e: E) -> Self {
+        ParseError::Syntax(Error::new(input, kind))
+    }
+}
+
+pub(super) fn to_bencode_error(e: Err<ParseError<'_>>, input: &[u8]) -> BencodeError {
+    match e {
+        Err::Error(e) | Err::Failure(e) => e.into_bencode_error(input),
+        Err::Incomplete(_) => BencodeError::Malformed {
+            offset: input.len(),
+        },
+    }
+}
+
+/// Only `-` may precede the digits.  Anything else, including `+`, fails on
+/// the non-zero digit check.
+fn non_zero_signed_digit1(input: &[u8]) -> IResult<'_, i64> {
+    let negative = opt(tag(b"-"));
+    let non_zero_peek = peek(one_of(b"123456789" as &[u8]));
+    // Parse the sign along with the digits so i64::MIN doesn't overflow.
+    let signed_digit1 = recognize(tuple((negative, non_zero_peek, digit1)));
+    map_res(signed_digit1, from_digit::<i64>)(input)
+}
+
+fn non_zero_padded_digit(input: &[u8]) -> IResult<'_, i64> {
+    let zero = value(0, verify(digit1, |x: &[u8]| x == b"0"));
+    alt((zero, non_zero_signed_digit1))(input)
+}
+
+/// Parses one value of any type.  `depth` is how many lists and dictionaries
+/// enclose this value.
+pub(super) fn parse_primitive<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, BEncodedType<'a>> {
+    let str_parser = map(
+        move |i| parse_limited_str(i, state),
+        |x: &[u8]| BEncodedType::String(x),
+    );
+    let int_parser = map(
+        move |i| parse_int_with(i, state),
+        |x: i64| BEncodedType::Integer(x),
+    );
+    let list_parser = map(
+        move |i| parse_list(i, depth, state),
+        |x: Vec<BEncodedType>| BEncodedType::List(x),
+    );
+    let dict_parser = map(
+        move |i| parse_dictionary(i, depth, state),
+        |x: DictRepr| BEncodedType::Dictionary(x),
+    );
+    let (rest, parsed) = alt((str_parser, int_parser, list_parser, dict_parser))(input)?;
+    state.take_entry()?;
+    Ok((rest, parsed))
+}
+
+fn parse_limited_str<'a>(input: &'a [u8], state: &ParseState) -> IResult<'a, &'a [u8]> {
+    state.check_length_prefix(input)?;
+    let (rest, s) = parse_str(input)?;
+    state.check_string(s)?;
+    Ok((rest, s))
+}
+
+/// Hand-written rather than `tuple((map_res(digit1, ..), tag(":")))` and
+/// `take`, since strings are most of what a torrent holds.  It fails the
+/// same way those combinators would, at the same positions.
+pub(super) fn parse_str(input: &[u8]) -> IResult<'_, &[u8]> {
+    let syntax = |input, kind| ParseError::Syntax(Error::new(input, kind));
+    let digits = input.iter().take_while(|x| x.is_ascii_digit()).count();
+    if digits == 0 {
+        return Err(Err::Error(syntax(input, ErrorKind::Digit)));
+    }
+    let len = input[..digits].iter().try_fold(0usize, |len, digit| {
+        len.checked_mul(10)?.checked_add(usize::from(digit - b'0'))
+    });
+    let len = len.ok_or_else(|| Err::Error(syntax(input, ErrorKind::MapRes)))?;
+    let suffix = match input[digits..].split_first() {
+        Some((b':', suffix)) => suffix,
+        _ => return Err(Err::Error(syntax(&input[digits..], ErrorKind::Tag))),
+    };
+    // Checked before slicing, so a huge declared length fails cleanly.
+    if suffix.len() < len {
+        return Err(limit_error(BencodeError::BadStringLength(len)));
+    }
+    Ok((&suffix[len..], &suffix[..len]))
+}
+
+pub(super) fn parse_int(input: &[u8]) -> IResult<'_, i64> {
+    let prefix = tag("i");
+    let suffix = tag("e");
+    terminated(preceded(prefix, cut(non_zero_padded_digit)), cut(suffix))(input)
+}
+
+pub(super) fn parse_list<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, Vec<BEncodedType<'a>>> {
+    let (input, _) = tag("l")(input)?;
+    let depth = state.descend(depth)?;
+    let suffix = tag("e");
+    let items = many0(move |i| parse_primitive(i, depth, state));
+    terminated(items, cut(suffix))(input)
+}
+
+pub(super) fn parse_dictionary<'a>(
+    input: &'a [u8],
+    depth: usize,
+    state: &ParseState,
+) -> IResult<'a, DictRepr<'a>> {
+    let (body, _) = tag("d")(input)?;
+    let depth = state.descend(depth)?;
+    let suffix = tag("e");
+    let kv = pair(
+        move |i| parse_limited_str(i, state),
+        cut(move |i| parse_primitive(i, depth, state)),
+    );
+    let items = many0(kv);
+    let (rest, pairs) = terminated(items, cut(suffix))(body)?;
+    let dict = DictRepr::from(pairs);
+    check_dict_order(&dict, input, state);
+    Ok((rest, dict))
+}
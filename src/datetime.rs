@@ -0,0 +1,123 @@
+//! A minimal UTC date and time, enough to show a torrent's `creation date`
+//! without pulling in a date crate.
+
+use core::fmt::{self, Display, Formatter};
+
+/// 0001-01-01T00:00:00Z, the earliest time `from_unix` accepts.
+const MIN_UNIX: i64 = -62_135_596_800;
+
+/// 9999-12-31T23:59:59Z, the latest time `from_unix` accepts.
+const MAX_UNIX: i64 = 253_402_300_799;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// A point in time in UTC, to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i32,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch, ignoring leap seconds.  Times
+    /// outside the years 1 to 9999 give `None`; in a torrent they're a
+    /// sign of a garbage timestamp rather than a real date.
+    pub fn from_unix(seconds: i64) -> Option<DateTime> {
+        if !(MIN_UNIX..=MAX_UNIX).contains(&seconds) {
+            return None;
+        }
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Some(DateTime {
+            year: year as i32,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        })
+    }
+}
+
+/// Turns days since 1970-01-01 into a proleptic Gregorian year, month and
+/// day, using Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Count from 0000-03-01 so leap days fall at the end of each year.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats as RFC 3339, e.g. `2020-09-13T12:26:40Z`.
+impl Display for DateTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_prelude::*;
+
+    mod from_unix {
+        use super::*;
+
+        #[test]
+        fn known_timestamps() {
+            let cases = [
+                (0, "1970-01-01T00:00:00Z"),
+                (-1, "1969-12-31T23:59:59Z"),
+                (951_782_400, "2000-02-29T00:00:00Z"),
+                (1_600_000_000, "2020-09-13T12:26:40Z"),
+            ];
+            for (seconds, expected) in &cases {
+                assert_eq!(
+                    DateTime::from_unix(*seconds).unwrap().to_string(),
+                    *expected
+                );
+            }
+        }
+
+        #[test]
+        fn fields() {
+            let date = DateTime::from_unix(1_600_000_000).unwrap();
+            assert_eq!((date.year, date.month, date.day), (2020, 9, 13));
+            assert_eq!((date.hour, date.minute, date.second), (12, 26, 40));
+        }
+
+        #[test]
+        fn range() {
+            let first = DateTime::from_unix(MIN_UNIX).unwrap();
+            assert_eq!(first.to_string(), "0001-01-01T00:00:00Z");
+            let last = DateTime::from_unix(MAX_UNIX).unwrap();
+            assert_eq!(last.to_string(), "9999-12-31T23:59:59Z");
+            assert_eq!(DateTime::from_unix(MIN_UNIX - 1), None);
+            assert_eq!(DateTime::from_unix(MAX_UNIX + 1), None);
+            assert_eq!(DateTime::from_unix(i64::MAX), None);
+        }
+    }
+}
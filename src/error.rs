@@ -0,0 +1,165 @@
+//! The error type shared by the library's APIs.
+
+use core::fmt::{Display, Formatter};
+use core::str::Utf8Error;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::alloc_prelude::*;
+use crate::bencode::BencodeError;
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The input isn't valid bencode.
+    Bencode(BencodeError),
+    /// A value isn't of the type asked for.  `expected` reads like "a list".
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// A dictionary doesn't have the key asked for.
+    KeyNotFound,
+    /// A string that has to be UTF-8 isn't.
+    Utf8(Utf8Error),
+    /// An integer doesn't fit the type it's read as.
+    OutOfRange { value: i64, target: &'static str },
+    /// Following a path of dictionary keys failed at `path`.
+    AtPath { path: String, source: Box<Error> },
+    /// A torrent file couldn't be read.
+    #[cfg(feature = "std")]
+    Open { path: PathBuf, source: io::Error },
+    /// Reading the torrent data failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// A gzipped torrent couldn't be decompressed.
+    Gzip(String),
+    /// The input decoded, but isn't a valid torrent or tracker response, or
+    /// a query expression is malformed or doesn't match.
+    Invalid(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bencode(e) => Display::fmt(e, f),
+            Error::WrongType { expected, found } => {
+                write!(f, "Type is {}, not {}", found, expected)
+            }
+            Error::KeyNotFound => f.write_str("Key not found"),
+            Error::Utf8(e) => write!(f, "UTF-8 decoding error: {:?}", e),
+            Error::OutOfRange { value, target } => {
+                write!(f, "integer {} is not a valid {}", value, target)
+            }
+            Error::AtPath { path, source } => write!(f, "{}: {}", path, source),
+            #[cfg(feature = "std")]
+            Error::Open { path, source } => {
+                write!(
+                    f,
+                    "failed to read torrent file {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            #[cfg(feature = "std")]
+            Error::Io(e) => Display::fmt(e, f),
+            Error::Gzip(message) | Error::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+// Every message above already includes its cause, so there's no `source`.
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<BencodeError> for Error {
+    fn from(e: BencodeError) -> Self {
+        Error::Bencode(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Builds an `Error::Invalid` from a format string, like `format!`.
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        $crate::error::Error::Invalid(alloc::format!($($arg)*))
+    };
+}
+
+pub(crate) use invalid;
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::bencode::{decode, BEncodedType};
+    use crate::metadata::Metadata;
+
+    mod variants {
+        use super::*;
+
+        #[test]
+        fn wrong_type() {
+            let err = BEncodedType::Integer(1).as_str().unwrap_err();
+            assert!(matches!(
+                err,
+                Error::WrongType {
+                    expected: "a string",
+                    found: "Integer"
+                }
+            ));
+        }
+
+        #[test]
+        fn key_not_found() {
+            let value = decode(b"de").unwrap();
+            assert!(matches!(value.dict_get("info"), Err(Error::KeyNotFound)));
+        }
+
+        #[test]
+        fn out_of_range() {
+            let err = BEncodedType::Integer(-5).as_u32().unwrap_err();
+            assert!(matches!(
+                err,
+                Error::OutOfRange {
+                    value: -5,
+                    target: "u32"
+                }
+            ));
+        }
+
+        #[test]
+        fn at_path() {
+            let value = decode(b"d4:infodee").unwrap();
+            match value.get_i64(&["info", "length"]).unwrap_err() {
+                Error::AtPath { path, source } => {
+                    assert_eq!(path, "info/length");
+                    assert!(matches!(*source, Error::KeyNotFound));
+                }
+                err => panic!("unexpected error {:?}", err),
+            }
+        }
+
+        #[test]
+        fn bencode() {
+            assert!(matches!(
+                Metadata::parse(b"x"),
+                Err(Error::Bencode(BencodeError::Malformed { offset: 0 }))
+            ));
+        }
+
+        #[test]
+        fn invalid() {
+            let buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:foo12:piece lengthi0e6:pieces0:ee";
+            assert!(matches!(Metadata::parse(buf), Err(Error::Invalid(_))));
+        }
+    }
+}
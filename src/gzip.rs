@@ -0,0 +1,465 @@
+//! A small gzip decompressor (RFC 1952 around RFC 1951 deflate), so
+//! `.torrent.gz` files can be read without pulling in a compression
+//! dependency.
+
+use crate::alloc_prelude::*;
+use crate::error::{Error, Result};
+
+/// Like `format!`, but builds an `Error::Gzip`.
+macro_rules! gzip_error {
+    ($($arg:tt)*) => {
+        Error::Gzip(format!($($arg)*))
+    };
+}
+
+const MAGIC: &[u8] = b"\x1f\x8b";
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+const MAX_BITS: usize = 15;
+
+/// The most `decompress` will inflate to.  Deflate can shrink runs of one
+/// byte about a thousandfold, so without a cap a small file could fill
+/// memory long before the bencode limits see it.  The largest real torrents
+/// are a few tens of megabytes.
+pub const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+/// Base lengths and extra bits for length codes 257..=285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits for distance codes 0..=29.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order code length code lengths are stored in for dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Whether `buf` starts like a gzip file.
+pub fn is_gzip(buf: &[u8]) -> bool {
+    buf.starts_with(MAGIC)
+}
+
+/// Decompresses a single-member gzip file, checking its CRC and length, and
+/// failing if it inflates to more than `MAX_DECOMPRESSED_LEN` bytes.
+pub fn decompress(buf: &[u8]) -> Result<Vec<u8>> {
+    decompress_with_limit(buf, MAX_DECOMPRESSED_LEN)
+}
+
+/// Like `decompress`, but failing past `max_len` bytes of output instead.
+pub fn decompress_with_limit(buf: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    if !is_gzip(buf) {
+        return Err(gzip_error!("Not a gzip file"));
+    }
+    let truncated = || gzip_error!("gzip file is truncated");
+    let header = buf.get(..10).ok_or_else(truncated)?;
+    if header[2] != 8 {
+        return Err(gzip_error!(
+            "Unsupported gzip compression method {}",
+            header[2]
+        ));
+    }
+    let flags = header[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = buf.get(pos..pos + 2).ok_or_else(truncated)?;
+        pos += 2 + usize::from(u16::from_le_bytes([len[0], len[1]]));
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let len = buf
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&x| x == 0))
+                .ok_or_else(truncated)?;
+            pos += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let mut reader = BitReader::new(buf.get(pos..).ok_or_else(truncated)?);
+    let out = inflate(&mut reader, max_len)?;
+    let trailer = reader.rest().get(..8).ok_or_else(truncated)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc != crc32(&out) {
+        return Err(gzip_error!("gzip CRC mismatch"));
+    }
+    if size != out.len() as u32 {
+        return Err(gzip_error!("gzip length mismatch"));
+    }
+    Ok(out)
+}
+
+/// Reads bits least significant first, as deflate packs them.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| gzip_error!("deflate stream is truncated"))?;
+            self.pos += 1;
+            self.bits |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops any bits left in the current byte.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    /// The bytes after the last one read from.
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+/// A canonical Huffman code, stored as how many codes there are of each
+/// length and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0u32);
+        for &count in &self.counts[1..] {
+            let count = u32::from(count);
+            code |= reader.bits(1)?;
+            if code < first + count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(gzip_error!("Invalid Huffman code in deflate stream"))
+    }
+}
+
+/// Fails once writing `len` more bytes would take `out` past `max_len`.
+fn check_output_len(out: &[u8], len: usize, max_len: usize) -> Result<()> {
+    if out.len() + len > max_len {
+        return Err(gzip_error!(
+            "gzip data inflates to more than {} bytes",
+            max_len
+        ));
+    }
+    Ok(())
+}
+
+fn inflate(reader: &mut BitReader, max_len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(reader, &mut out, max_len)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                huffman_block(reader, &mut out, max_len, &lengths, &distances)?
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(reader)?;
+                huffman_block(reader, &mut out, max_len, &lengths, &distances)?
+            }
+            _ => return Err(gzip_error!("Invalid deflate block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored_block(reader: &mut BitReader, out: &mut Vec<u8>, max_len: usize) -> Result<()> {
+    reader.align();
+    let rest = reader.rest();
+    let header = rest
+        .get(..4)
+        .ok_or_else(|| gzip_error!("deflate stream is truncated"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    if len != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(gzip_error!("Stored deflate block has a corrupt length"));
+    }
+    let data = rest
+        .get(4..4 + usize::from(len))
+        .ok_or_else(|| gzip_error!("deflate stream is truncated"))?;
+    check_output_len(out, data.len(), max_len)?;
+    out.extend_from_slice(data);
+    reader.pos += 4 + usize::from(len);
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[i] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, repeat) = match code_lengths.decode(reader)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| gzip_error!("Repeated code length with nothing to repeat"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(len);
+        }
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(gzip_error!("Code lengths overrun the dynamic block header"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn huffman_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    max_len: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<()> {
+    loop {
+        let symbol = usize::from(lengths.decode(reader)?);
+        if symbol < 256 {
+            check_output_len(out, 1, max_len)?;
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(gzip_error!("Invalid length code in deflate stream"));
+        }
+        let len = usize::from(LENGTH_BASE[symbol])
+            + reader.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+        let symbol = usize::from(distances.decode(reader)?);
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(gzip_error!("Invalid distance code in deflate stream"));
+        }
+        let distance = usize::from(DISTANCE_BASE[symbol])
+            + reader.bits(u32::from(DISTANCE_EXTRA[symbol]))? as usize;
+        if distance > out.len() {
+            return Err(gzip_error!(
+                "Distance reaches back before the start of the output"
+            ));
+        }
+        check_output_len(out, len, max_len)?;
+        // The copy can overlap what it's producing, so go byte by byte.
+        let start = out.len() - distance;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+/// The CRC-32 (IEEE) gzip stores for the uncompressed data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    mod decompress {
+        use super::*;
+
+        #[test]
+        fn stored_block() {
+            let buf = from_hex(
+                "1f8b0800000000000403011100eeff68656c6c6f2068656c6c6f2068656c6c6f8088f9e511000000",
+            );
+            assert_eq!(decompress(&buf).unwrap(), b"hello hello hello");
+        }
+
+        #[test]
+        fn fixed_huffman_block() {
+            let buf = from_hex("1f8b0800000000000203cb48cdc9c957c84090008088f9e511000000");
+            assert_eq!(decompress(&buf).unwrap(), b"hello hello hello");
+        }
+
+        #[test]
+        fn corrupt_crc() {
+            let mut buf = from_hex("1f8b0800000000000203cb48cdc9c957c84090008088f9e511000000");
+            let crc = buf.len() - 8;
+            buf[crc] ^= 1;
+            assert!(decompress(&buf).is_err());
+        }
+
+        #[test]
+        fn truncated() {
+            let buf = from_hex("1f8b0800000000000203cb48cdc9c957c840");
+            assert!(decompress(&buf).is_err());
+        }
+
+        /// A fixed Huffman stream of one zero byte followed by `copies`
+        /// repeats of the longest back-reference, 258 more zeros each.
+        fn zeros(copies: usize) -> Vec<u8> {
+            let mut out = from_hex("1f8b08000000000000ff");
+            let (mut bits, mut count) = (0u32, 0);
+            // Huffman codes go in most significant bit first.
+            let mut put = |code: u32, len: u32, reversed: bool| {
+                for i in 0..len {
+                    let shift = if reversed { len - 1 - i } else { i };
+                    bits |= (code >> shift & 1) << count;
+                    count += 1;
+                    if count == 8 {
+                        out.push(bits as u8);
+                        bits = 0;
+                        count = 0;
+                    }
+                }
+            };
+            put(0b011, 3, false); // Final block, fixed codes.
+            put(0x30, 8, true); // Literal 0.
+            for _ in 0..copies {
+                put(0xC5, 8, true); // Length 258.
+                put(0, 5, true); // Distance 1.
+            }
+            put(0, 7, true); // End of block.
+            put(0, 7, false);
+            out
+        }
+
+        #[test]
+        fn oversized_output() {
+            let limit = 1024 * 1024;
+            // About 13 KiB that would inflate to 2 MiB.
+            let buf = zeros(2 * limit / 258);
+            assert!(buf.len() < 16 * 1024);
+            let err = decompress_with_limit(&buf, limit).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "gzip data inflates to more than 1048576 bytes"
+            );
+        }
+
+        #[test]
+        fn output_within_limit() {
+            // Without a trailer this can't succeed, but it gets past the limit.
+            let buf = zeros(10);
+            let err = decompress_with_limit(&buf, 258 * 10 + 1).unwrap_err();
+            assert_eq!(err.to_string(), "gzip file is truncated");
+        }
+
+        #[test]
+        fn not_gzip() {
+            assert!(decompress(b"d4:infodee").is_err());
+        }
+    }
+
+    mod crc32 {
+        use super::*;
+
+        #[test]
+        fn check_value() {
+            assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        }
+    }
+}
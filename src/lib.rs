@@ -0,0 +1,33 @@
+// Without `std` only the bencode core is built, on top of `alloc`.  Tests
+// always have std.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod bencode;
+pub mod datetime;
+pub mod error;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+#[cfg(feature = "std")]
+pub mod metadata;
+pub mod query;
+pub mod sha1;
+pub mod sha256;
+#[cfg(feature = "std")]
+pub mod torrent;
+#[cfg(feature = "std")]
+pub mod tracker;
+
+pub use error::{Error, Result};
+
+/// What the std prelude would otherwise provide, for the modules that also
+/// build under `no_std`.  Not every module needs all of it.
+#[allow(unused_imports)]
+mod alloc_prelude {
+    pub use alloc::borrow::{Cow, ToOwned};
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
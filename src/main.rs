@@ -1,17 +1,62 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::env;
-use std::fs::File;
-use std::io::Read;
 
-mod bencode;
-mod metadata;
+use tquery::bencode::BEncodedType;
+use tquery::metadata::Severity;
+use tquery::query::{to_json, Query};
+use tquery::sha1::{to_base32, to_hex};
+use tquery::torrent::Torrent;
+use tquery::Error;
+
+const USAGE: &str = "Usage: tquery <file.torrent>
+       tquery --tree [--redact] <file.torrent>
+       tquery --query [--json] <expr> <file.torrent>
+       tquery --lint <file.torrent>
+       tquery info-hash [--base32] <file.torrent>";
 
 fn main() -> Result<()> {
-    let filename = env::args().skip(1).next().unwrap();
-    let mut file = File::open(&filename).unwrap();
-    let mut contents = vec![];
-    file.read_to_end(&mut contents).unwrap();
-    let decoded = bencode::decode(&contents)?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("info-hash") => info_hash(&args[1..]),
+        Some("--tree") => match &args[1..] {
+            [filename] => tree(filename, false),
+            [flag, filename] if flag == "--redact" => tree(filename, true),
+            _ => Err(anyhow!(USAGE)),
+        },
+        Some("--query") => match &args[1..] {
+            [expr, filename] => query(expr, filename, false),
+            [flag, expr, filename] if flag == "--json" => query(expr, filename, true),
+            _ => Err(anyhow!(USAGE)),
+        },
+        Some("--lint") => match &args[1..] {
+            [filename] => lint(filename),
+            _ => Err(anyhow!(USAGE)),
+        },
+        Some(filename) => dump(filename),
+        None => Err(anyhow!(USAGE)),
+    }
+}
+
+/// Prints just the info-hash, as hex or with `--base32` as base32.
+fn info_hash(args: &[String]) -> Result<()> {
+    let (base32, filename) = match args {
+        [flag, filename] if flag == "--base32" => (true, filename),
+        [filename] => (false, filename),
+        _ => return Err(anyhow!(USAGE)),
+    };
+    let hash = open(filename)?.info_hash();
+    if base32 {
+        println!("{}", to_base32(&hash));
+    } else {
+        println!("{}", to_hex(&hash));
+    }
+    Ok(())
+}
+
+fn dump(filename: &str) -> Result<()> {
+    let torrent = open(filename)?;
+    let meta = torrent.metadata();
+    let decoded = meta.root();
     println!("Top level type is {:?}", decoded.type_str());
     println!("Keys of the top level thing: {:?}", decoded.dict_keys()?);
     let info = decoded.dict_get("info")?;
@@ -25,10 +70,122 @@ fn main() -> Result<()> {
     println!();
     println!();
     println!();
-    let meta = metadata::Metadata::parse(&contents);
     println!("Metadata: {:?}", meta);
 
     println!("The whole thing: {:#?}", decoded);
 
     Ok(())
 }
+
+/// Prints what a query expression like `info.files[0].length` picks out of
+/// the torrent, as text or with `json` as JSON.
+fn query(expr: &str, filename: &str, json: bool) -> Result<()> {
+    let query = Query::parse(expr)?;
+    let torrent = open(filename)?;
+    let result = query.eval(torrent.metadata().root())?;
+    if json {
+        println!("{}", to_json(&result));
+    } else {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+/// Prints what `sanity_check` finds, failing if any of it is an error.
+fn lint(filename: &str) -> Result<()> {
+    let torrent = open(filename)?;
+    let diagnostics = torrent.metadata().sanity_check();
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|x| x.severity == Severity::Error) {
+        return Err(anyhow!("{} has errors", filename));
+    }
+    Ok(())
+}
+
+/// Prints the decoded torrent as an indented tree, one value per line.  With
+/// `redact`, piece hashes are left out entirely, for sharing in bug reports.
+fn tree(filename: &str, redact: bool) -> Result<()> {
+    let torrent = open(filename)?;
+    let meta = torrent.metadata();
+    let root = meta.root();
+    let mut out = String::new();
+    if redact {
+        // The hashes are gone already, so there's nothing to collapse.
+        write_tree(
+            &mut out,
+            "",
+            &root.redacted().as_borrowed(),
+            0,
+            TreePos::Other,
+        );
+    } else {
+        write_tree(&mut out, "", root, 0, TreePos::Root);
+    }
+    print!("{}", out);
+    Ok(())
+}
+
+/// Where `write_tree` is in the torrent, so it can tell `info.pieces` from
+/// any other key that happens to be called `pieces`.
+#[derive(Clone, Copy, PartialEq)]
+enum TreePos {
+    Root,
+    Info,
+    Pieces,
+    Other,
+}
+
+fn write_tree(out: &mut String, label: &str, value: &BEncodedType, depth: usize, pos: TreePos) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(label);
+    match value {
+        // Thousands of hashes would drown out everything else.
+        BEncodedType::String(x) if pos == TreePos::Pieces && x.len() % 20 == 0 => {
+            out.push_str(&format!("<pieces: {} × 20 bytes>\n", x.len() / 20));
+        }
+        BEncodedType::String(x) if pos == TreePos::Pieces => {
+            out.push_str(&format!("<pieces: {} bytes>\n", x.len()));
+        }
+        BEncodedType::String(x) => match std::str::from_utf8(x) {
+            Ok(text) => out.push_str(&format!("String {:?}\n", text)),
+            Err(_) => out.push_str(&format!("<{} bytes, hex: {}>\n", x.len(), to_hex(x))),
+        },
+        BEncodedType::Integer(x) => out.push_str(&format!("Integer {}\n", x)),
+        BEncodedType::List(items) => {
+            out.push_str(&format!("List ({} items)\n", items.len()));
+            for (i, item) in items.iter().enumerate() {
+                write_tree(out, &format!("{}: ", i), item, depth + 1, TreePos::Other);
+            }
+        }
+        BEncodedType::Dictionary(entries) => {
+            out.push_str(&format!("Dictionary ({} entries)\n", entries.len()));
+            for (key, item) in entries {
+                let label = format!("{}: ", String::from_utf8_lossy(key));
+                let child = match (pos, *key) {
+                    (TreePos::Root, b"info") => TreePos::Info,
+                    (TreePos::Info, b"pieces") => TreePos::Pieces,
+                    _ => TreePos::Other,
+                };
+                write_tree(out, &label, item, depth + 1, child);
+            }
+        }
+    }
+}
+
+/// Opens `filename`, with a friendlier error for an empty or blank file.
+/// Any other decoding error is passed through as it is.
+fn open(filename: &str) -> Result<Torrent> {
+    Torrent::open(filename).map_err(|e| match e {
+        Error::Bencode(_) if is_blank(filename) => {
+            anyhow!("{} is empty or not bencoded", filename)
+        }
+        e => e.into(),
+    })
+}
+
+/// Whether `filename` holds nothing but whitespace, if anything.
+fn is_blank(filename: &str) -> bool {
+    std::fs::read(filename).is_ok_and(|bytes| bytes.iter().all(u8::is_ascii_whitespace))
+}
@@ -1,39 +1,2373 @@
-use std::fmt::Debug;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::str;
 
-use crate::bencode::{decode, BEncodedType};
-use anyhow::Result;
+use crate::bencode::{bencode_key_cmp, decode, encode, raw_dict_entries, BEncodedType};
+use crate::datetime::DateTime;
+use crate::error::{invalid, Error, Result};
+use crate::sha1::{self, to_hex, Sha1};
+use crate::sha256::{self, Sha256};
+
+/// Every entry in `info.pieces` is a SHA-1 digest.
+const PIECE_HASH_LEN: usize = 20;
+
+/// The range of piece lengths real clients expect to see.
+const MIN_PIECE_LENGTH: u32 = 16 * 1024;
+const MAX_PIECE_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// BEP-9 sends the `info` dictionary in pieces of this size, except the last.
+const METADATA_PIECE_LEN: usize = 16 * 1024;
+
+/// Which BitTorrent protocol versions a torrent supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentVersion {
+    V1,
+    /// BEP-52, with SHA-256 hashes and a `file tree`.
+    V2,
+    /// Carries both v1 `pieces` and v2 `meta version` data.
+    Hybrid,
+}
+
+/// An info-hash computed with the algorithm a torrent's version calls for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoHash {
+    Sha1([u8; sha1::DIGEST_LEN]),
+    Sha256([u8; sha256::DIGEST_LEN]),
+}
+
+impl InfoHash {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            InfoHash::Sha1(x) => x,
+            InfoHash::Sha256(x) => x,
+        }
+    }
+}
+
+/// How much a `Diagnostic` matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth knowing, but nothing's wrong.
+    Info,
+    /// Clients may handle it badly.
+    Warning,
+    /// The torrent is inconsistent and can't be downloaded as is.
+    Error,
+}
+
+/// One finding from `Metadata::sanity_check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+/// Formats like a compiler message, e.g. `warning: torrent has no trackers`.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
 
 pub struct Metadata<'a> {
     be: BEncodedType<'a>,
-    pub announce: &'a str,
-    pub name: &'a str,
+    info_bytes: &'a [u8],
+    pub announce: Option<&'a str>,
+    /// Tiers of tracker URLs from `announce-list` (BEP-12), empty if absent.
+    pub announce_list: Vec<Vec<Cow<'a, str>>>,
+    pub info: InfoMetadata<'a>,
+    pub publisher: Option<&'a str>,
+    pub publisher_url: Option<&'a str>,
+    /// `creation date`, in seconds since the Unix epoch.
+    pub creation_date: Option<i64>,
+    nodes: Vec<(&'a str, u16)>,
+    /// The `dn` of the magnet link this was fetched for, if any.
+    magnet_name: Option<String>,
 }
 
 impl<'a> Metadata<'a> {
     pub fn parse(buf: &'a [u8]) -> Result<Metadata<'a>> {
         let be = decode(buf)?;
-        let announce = be.dict_get("announce")?.as_str()?;
-        let info = be.dict_get("info")?;
-        let name = info.dict_get("name")?.as_str()?;
+        // Catches being pointed at bencode that isn't a torrent at all.
+        if !matches!(be, BEncodedType::Dictionary(_)) {
+            return Err(invalid!(
+                "top-level value must be a dictionary, found {}",
+                be.type_str().to_ascii_lowercase()
+            ));
+        }
+        let announce = optional_str(&be, "announce")?;
+        let announce_list = parse_announce_list(&be)?;
+        let nodes = parse_nodes(&be)?;
+        // Trackerless torrents find peers through the DHT nodes instead.
+        if announce.is_none() && announce_list.is_empty() && be.dict_get_opt("nodes")?.is_none() {
+            return Err(invalid!(
+                "Torrent has no announce, announce-list or DHT nodes"
+            ));
+        }
+        let info_be = be.dict_get("info")?;
+        // A piece count that doesn't match the length is left for
+        // `sanity_check` to report, so a damaged torrent can still be
+        // inspected.
+        let info = InfoMetadata::parse(info_be)?;
+        let publisher = optional_str(&be, "publisher")?;
+        let publisher_url = optional_str(&be, "publisher-url")?;
+        let creation_date = optional_int(&be, "creation date")?;
+        // Like `dict_get`, take the last `info` if it's repeated, so the
+        // hash covers the dictionary the rest of the metadata came from.
+        let info_bytes = raw_dict_entries(buf)?
+            .into_iter()
+            .rev()
+            .find(|(key, _)| *key == b"info")
+            .map(|(_, value)| value)
+            .ok_or(Error::KeyNotFound)?;
 
         Ok(Metadata {
-            be: be,
+            be,
+            info_bytes,
             announce,
-            name,
+            announce_list,
+            info,
+            publisher,
+            publisher_url,
+            creation_date,
+            nodes,
+            magnet_name: None,
+        })
+    }
+
+    /// Metadata for a bare `info` dictionary, such as peers send with BEP-9,
+    /// rather than a whole torrent file.  It has no trackers or other
+    /// top level fields, and its `info_bytes` are all of `buf`.
+    pub fn from_info_bytes(buf: &'a [u8]) -> Result<Metadata<'a>> {
+        let info_be = decode(buf)?;
+        info_be.as_dict()?;
+        let info = InfoMetadata::parse(&info_be)?;
+        Ok(Metadata {
+            be: BEncodedType::Dictionary(vec![(&b"info"[..], info_be)].into()),
+            info_bytes: buf,
+            announce: None,
+            announce_list: Vec::new(),
+            info,
+            publisher: None,
+            publisher_url: None,
+            creation_date: None,
+            nodes: Vec::new(),
+            magnet_name: None,
+        })
+    }
+
+    /// Metadata for an `info` dictionary fetched for a magnet link, keeping
+    /// the link's `dn` as a fallback name and its `tr` trackers.
+    pub fn from_magnet(
+        buf: &'a [u8],
+        display_name: Option<&str>,
+        trackers: &[String],
+    ) -> Result<Metadata<'a>> {
+        let mut meta = Metadata::from_info_bytes(buf)?;
+        meta.merge_trackers(trackers);
+        meta.magnet_name = display_name.filter(|dn| !dn.is_empty()).map(str::to_string);
+        Ok(meta)
+    }
+
+    /// The decoded top level dictionary this metadata was read from.
+    pub fn root(&self) -> &BEncodedType<'a> {
+        &self.be
+    }
+
+    /// The torrent's name as stored, which isn't necessarily UTF-8.
+    pub fn name_bytes(&self) -> &'a [u8] {
+        self.info.name
+    }
+
+    /// The torrent's name, with invalid UTF-8 replaced by U+FFFD.
+    pub fn name_lossy(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.info.name)
+    }
+
+    /// A label for showing the torrent in a UI, never empty: the name if
+    /// there is one, else the magnet link's `dn`, else the hex info-hash.
+    pub fn display_name(&self) -> Cow<'_, str> {
+        if !self.info.name.is_empty() {
+            return self.name_lossy();
+        }
+        match &self.magnet_name {
+            Some(dn) => Cow::Borrowed(dn),
+            None => Cow::Owned(to_hex(self.native_info_hash().as_bytes())),
+        }
+    }
+
+    /// The torrent's name, failing if it isn't valid UTF-8.
+    pub fn name_utf8(&self) -> Result<&'a str> {
+        str::from_utf8(self.info.name).map_err(Error::Utf8)
+    }
+
+    /// `creation_date` as a UTC date, or `None` if it's absent or too far
+    /// from the present to be a real date.
+    pub fn creation_datetime(&self) -> Option<DateTime> {
+        self.creation_date.and_then(DateTime::from_unix)
+    }
+
+    /// Lints the torrent, collecting everything that's odd but didn't stop
+    /// it from parsing, most severe first.
+    pub fn sanity_check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        if let Err(e) = self.info.check_piece_count() {
+            diagnostics.push(Diagnostic::new(Severity::Error, e.to_string()));
+        }
+        if let Some(message) = self.info.check_piece_length() {
+            diagnostics.push(Diagnostic::new(Severity::Warning, message));
+        }
+        if self.announce_urls().is_empty() {
+            let message = if self.nodes.is_empty() {
+                "torrent has no trackers or DHT nodes"
+            } else {
+                "torrent has no trackers, only DHT nodes"
+            };
+            diagnostics.push(Diagnostic::new(Severity::Warning, message));
+        }
+        for file in self.info.files().iter().filter(|file| file.length == 0) {
+            let path: Vec<_> = file
+                .path
+                .iter()
+                .map(|x| String::from_utf8_lossy(x))
+                .collect();
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("file {} is empty", path.join("/")),
+            ));
+        }
+        if self.creation_date.is_none() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Info,
+                "torrent has no creation date",
+            ));
+        }
+        diagnostics.sort_by_key(|x| Reverse(x.severity));
+        diagnostics
+    }
+
+    /// Shorthand for `self.info.piece_length()`.
+    pub fn piece_length(&self) -> u32 {
+        self.info.piece_length()
+    }
+
+    /// Every file the torrent downloads, in order, whether it's a single or
+    /// multi-file torrent.  Paths are relative to the download directory and
+    /// checked like `InfoMetadata::file_offsets` checks them.
+    pub fn files(&self) -> Result<impl Iterator<Item = FileInfo>> {
+        Ok(self
+            .info
+            .file_offsets()?
+            .into_iter()
+            .map(|(path, _, length)| FileInfo { path, length }))
+    }
+
+    /// Shorthand for `self.info.piece_count()`.
+    pub fn num_pieces(&self) -> usize {
+        self.info.piece_count()
+    }
+
+    /// The `info` dictionary exactly as it appears in the original buffer,
+    /// which is what the info-hash is computed over.
+    pub fn info_bytes(&self) -> &'a [u8] {
+        self.info_bytes
+    }
+
+    /// The decoded `info` dictionary on its own, e.g. to edit and re-encode
+    /// it.  For a canonically encoded torrent, `encode` gives back exactly
+    /// `info_bytes`.
+    pub fn info_document(&self) -> BEncodedType<'a> {
+        self.be
+            .dict_get("info")
+            .expect("parsed metadata always has an info dictionary")
+            .clone()
+    }
+
+    /// Feeds the raw `info` dictionary into `hasher`, for hashing it with
+    /// a digest of the caller's choosing.  `Sha1` and `Sha256` both
+    /// implement `Write`, as do most hashers from other crates.
+    pub fn hash_info_into<W: Write + ?Sized>(&self, hasher: &mut W) -> Result<()> {
+        hasher.write_all(self.info_bytes)?;
+        Ok(())
+    }
+
+    /// The SHA-1 of the raw `info` dictionary, which identifies the torrent.
+    pub fn info_hash(&self) -> [u8; sha1::DIGEST_LEN] {
+        let mut hasher = Sha1::new();
+        self.hash_info_into(&mut hasher)
+            .expect("hashing in memory can't fail");
+        hasher.finish()
+    }
+
+    /// The SHA-256 of the raw `info` dictionary, for v2 and hybrid torrents.
+    pub fn info_hash_v2(&self) -> Option<[u8; sha256::DIGEST_LEN]> {
+        match self.version() {
+            TorrentVersion::V1 => None,
+            TorrentVersion::V2 | TorrentVersion::Hybrid => {
+                let mut hasher = Sha256::new();
+                self.hash_info_into(&mut hasher)
+                    .expect("hashing in memory can't fail");
+                Some(hasher.finish())
+            }
+        }
+    }
+
+    /// `info_hash_v2` cut down to 20 bytes, the form v2 hashes take
+    /// wherever a v1-sized hash is expected (e.g. the tracker protocol).
+    pub fn info_hash_v2_truncated(&self) -> Option<[u8; sha1::DIGEST_LEN]> {
+        self.info_hash_v2().map(|hash| {
+            let mut truncated = [0; sha1::DIGEST_LEN];
+            truncated.copy_from_slice(&hash[..sha1::DIGEST_LEN]);
+            truncated
         })
     }
+
+    pub fn version(&self) -> TorrentVersion {
+        self.info.version
+    }
+
+    /// The info-hash for this torrent's version: SHA-1 for v1, and SHA-256
+    /// for v2 and hybrid torrents.  Hybrids are also reachable by `info_hash`.
+    pub fn native_info_hash(&self) -> InfoHash {
+        match self.info_hash_v2() {
+            Some(hash) => InfoHash::Sha256(hash),
+            None => InfoHash::Sha1(self.info_hash()),
+        }
+    }
+
+    /// Whether `other` describes the same content, i.e. has the same
+    /// info-hash, regardless of trackers or other top level keys.
+    pub fn same_content(&self, other: &Metadata) -> bool {
+        self.info_hash() == other.info_hash()
+    }
+
+    /// A short identifier for deduplicating a collection: the first 12 hex
+    /// characters of the info-hash, then the total length, e.g.
+    /// `5c14691c7957-40000`.  It is not an info-hash and can't stand in for
+    /// one in the protocol or in magnet links.
+    pub fn fingerprint(&self) -> String {
+        let hash = to_hex(&self.info_hash());
+        format!("{}-{}", &hash[..12], self.info.total_length())
+    }
+
+    /// Every tracker URL, `announce` first and then each `announce-list`
+    /// tier in order, without duplicates.
+    pub fn announce_urls(&self) -> Vec<&str> {
+        let mut urls: Vec<&str> = Vec::new();
+        let tiers = self.announce_list.iter().flatten().map(|url| url.as_ref());
+        for url in self.announce.into_iter().chain(tiers) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// The `announce-list` tiers as they are, for trying trackers tier by
+    /// tier as BEP-12 describes.  Without an `announce-list`, `announce` is
+    /// the only tier.
+    pub fn tracker_tiers(&self) -> Vec<Vec<&str>> {
+        if self.announce_list.is_empty() {
+            return self.announce.into_iter().map(|url| vec![url]).collect();
+        }
+        self.announce_list
+            .iter()
+            .map(|tier| tier.iter().map(|url| url.as_ref()).collect())
+            .collect()
+    }
+
+    /// Encodes the torrent again, with `announce` and `announce-list` as
+    /// they are now.  The `info` dictionary is copied over byte for byte
+    /// rather than re-encoded, so the info-hash stays the same.  Other top
+    /// level keys are canonical, keeping only the last of a repeated key.
+    pub fn to_bencoded(&self) -> Vec<u8> {
+        let mut entries: Vec<(&[u8], Cow<[u8]>)> = self
+            .be
+            .as_dict()
+            .expect("the top level parsed as a dictionary")
+            .iter_canonical()
+            .filter(|(key, _)| !matches!(*key, b"announce" | b"announce-list" | b"info"))
+            .map(|(key, value)| (*key, Cow::Owned(encode(value))))
+            .collect();
+        entries.push((b"info", Cow::Borrowed(self.info_bytes)));
+        if let Some(announce) = self.announce {
+            entries.push((
+                b"announce",
+                Cow::Owned(encode(&BEncodedType::string(announce))),
+            ));
+        }
+        if !self.announce_list.is_empty() {
+            let tiers: BEncodedType = self
+                .announce_list
+                .iter()
+                .map(|tier| BEncodedType::list(tier.iter().map(|url| BEncodedType::string(url))))
+                .collect();
+            entries.push((b"announce-list", Cow::Owned(encode(&tiers))));
+        }
+        entries.sort_by(|a, b| bencode_key_cmp(a.0, b.0));
+
+        let mut buf = b"d".to_vec();
+        for (key, value) in entries {
+            buf.extend(encode(&BEncodedType::bytes(key)));
+            buf.extend_from_slice(&value);
+        }
+        buf.push(b'e');
+        buf
+    }
+
+    /// The DHT bootstrap nodes of a trackerless torrent (BEP-5), as host and
+    /// port pairs.  Empty if the torrent has no `nodes`.
+    pub fn nodes(&self) -> Vec<(String, u16)> {
+        self.nodes
+            .iter()
+            .map(|(host, port)| (host.to_string(), *port))
+            .collect()
+    }
+
+    /// Adds `url` to the first `announce-list` tier, unless it's already one
+    /// of the torrent's trackers.  Write the result out with `to_bencoded`.
+    pub fn add_tracker(&mut self, url: &str) {
+        if self.announce_urls().contains(&url) {
+            return;
+        }
+        self.carry_over_announce();
+        match self.announce_list.first_mut() {
+            Some(tier) => tier.push(Cow::Owned(url.to_string())),
+            None => self.announce_list.push(vec![Cow::Owned(url.to_string())]),
+        }
+    }
+
+    /// Adds `other_trackers` (say, the `tr` parameters of a magnet link for
+    /// the same torrent) as a new `announce-list` tier, skipping any that
+    /// are already known.
+    pub fn merge_trackers(&mut self, other_trackers: &[String]) {
+        self.carry_over_announce();
+
+        let mut tier: Vec<Cow<'a, str>> = Vec::new();
+        for tracker in other_trackers {
+            let known = self.announce == Some(tracker.as_str())
+                || self
+                    .announce_list
+                    .iter()
+                    .flatten()
+                    .any(|url| url == tracker)
+                || tier.iter().any(|url| url == tracker);
+            if !known {
+                tier.push(Cow::Owned(tracker.clone()));
+            }
+        }
+        if !tier.is_empty() {
+            self.announce_list.push(tier);
+        }
+    }
+
+    /// Clients ignore `announce` once there's an `announce-list`, so it has
+    /// to become a tier of its own before the first one is added.
+    fn carry_over_announce(&mut self) {
+        if self.announce_list.is_empty() {
+            if let Some(announce) = self.announce {
+                self.announce_list.push(vec![Cow::Borrowed(announce)]);
+            }
+        }
+    }
 }
 
 impl Debug for Metadata<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Metadata")
             .field("announce", &self.announce)
-            .field("name", &self.name)
+            .field("announce_list", &self.announce_list)
+            .field("name", &self.name_lossy())
+            .field("info", &self.info)
+            .field("publisher", &self.publisher)
+            .field("publisher_url", &self.publisher_url)
+            .field("creation_date", &self.creation_date)
+            .field("nodes", &self.nodes)
+            .field("magnet_name", &self.magnet_name)
             .finish()
     }
 }
 
-struct InfoMetadata<'a> {
+pub struct InfoMetadata<'a> {
+    version: TorrentVersion,
+    name: &'a [u8],
     piece_length: u32,
-    pieces: Vec<&'a [u8]>,
+    /// The concatenated piece hashes.
+    pieces: &'a [u8],
+    total_length: u64,
+    files: Vec<FileEntry<'a>>,
+}
+
+/// A file the torrent downloads, the same for single and multi-file
+/// torrents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+/// One file of a multi-file torrent.
+#[derive(Debug, PartialEq)]
+pub struct FileEntry<'a> {
+    pub length: u64,
+    /// Path components, relative to the torrent's `name` directory.
+    pub path: Vec<&'a [u8]>,
+    /// The optional `md5sum` some creators add for legacy verification.
+    pub md5sum: Option<[u8; 16]>,
+}
+
+impl<'a> FileEntry<'a> {
+    /// The raw path components, exactly as the torrent lists them.
+    pub fn components(&self) -> &[&'a [u8]] {
+        &self.path
+    }
+
+    /// The path relative to the torrent's directory, refusing components
+    /// that are empty, `.`, `..`, absolute or contain a separator, any of
+    /// which could write outside the download directory.
+    pub fn safe_path(&self) -> Result<PathBuf> {
+        if self.path.is_empty() {
+            return Err(invalid!("File has an empty path"));
+        }
+        self.path.iter().map(|x| safe_component(x)).collect()
+    }
+
+    fn parse(file: &BEncodedType<'a>) -> Result<FileEntry<'a>> {
+        let length = file_length(file.dict_get("length")?)?;
+        let path = preferring_utf8(file, "path")?
+            .as_list()?
+            .iter()
+            .map(|component| component.as_str_bytes())
+            .collect::<Result<_>>()?;
+        let md5sum = match file.dict_get_opt("md5sum")? {
+            Some(md5sum) => Some(parse_md5sum(md5sum.as_str_bytes()?)?),
+            None => None,
+        };
+        Ok(FileEntry {
+            length,
+            path,
+            md5sum,
+        })
+    }
+}
+
+impl<'a> InfoMetadata<'a> {
+    pub fn parse(info: &BEncodedType<'a>) -> Result<InfoMetadata<'a>> {
+        let name = preferring_utf8(info, "name")?.as_str_bytes()?;
+        let piece_length = info
+            .dict_get("piece length")?
+            .as_u32()
+            .map_err(|e| invalid!("Invalid piece length: {}", e))?;
+        if piece_length == 0 {
+            return Err(invalid!("Invalid piece length: must be greater than 0"));
+        }
+
+        let version = parse_version(info)?;
+        // Pure v2 torrents hash pieces per file in `piece layers` instead.
+        let pieces = match (info.dict_get_opt("pieces")?, version) {
+            (None, TorrentVersion::V2) => &[][..],
+            _ => info.dict_get("pieces")?.as_str_bytes()?,
+        };
+        if pieces.len() % PIECE_HASH_LEN != 0 {
+            return Err(invalid!(
+                "pieces is {} bytes long, which is not a multiple of {}",
+                pieces.len(),
+                PIECE_HASH_LEN
+            ));
+        }
+
+        // Pure v2 torrents describe their files in `file tree` instead.
+        match (info.dict_get_opt("length")?, info.dict_get_opt("files")?) {
+            (Some(_), Some(_)) => return Err(invalid!("torrent declares both length and files")),
+            (None, None) if version != TorrentVersion::V2 => {
+                return Err(invalid!("torrent declares neither length nor files"))
+            }
+            _ => {}
+        }
+
+        let (total_length, files) = match (info.dict_get_opt("length")?, version) {
+            (Some(length), _) => (file_length(length)?, Vec::new()),
+            (None, TorrentVersion::V2) => {
+                let mut files = Vec::new();
+                parse_file_tree(info.dict_get("file tree")?, &mut Vec::new(), &mut files)?;
+                let total_length = total_length(&files)?;
+                // A lone file named after the torrent is a single-file torrent.
+                if let [file] = &files[..] {
+                    if file.path == [name] {
+                        files.clear();
+                    }
+                }
+                (total_length, files)
+            }
+            (None, _) => {
+                let files = info
+                    .dict_get("files")?
+                    .as_list()?
+                    .iter()
+                    .map(FileEntry::parse)
+                    .collect::<Result<Vec<_>>>()?;
+                (total_length(&files)?, files)
+            }
+        };
+
+        Ok(InfoMetadata {
+            version,
+            name,
+            piece_length,
+            pieces,
+            total_length,
+            files,
+        })
+    }
+
+    /// The files of a multi-file torrent, empty for single-file torrents.
+    pub fn files(&self) -> &[FileEntry<'a>] {
+        &self.files
+    }
+
+    pub fn piece_length(&self) -> u32 {
+        self.piece_length
+    }
+
+    /// The size of all files combined.
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    /// Every file's path relative to the download directory, along with
+    /// where it starts in the concatenated content and how long it is.
+    /// Fails if the name or any path isn't safe to join onto the download
+    /// directory, as `FileEntry::safe_path` checks.
+    pub fn file_offsets(&self) -> Result<Vec<(PathBuf, u64, u64)>> {
+        let name = PathBuf::from(safe_component(self.name)?);
+        if self.files.is_empty() {
+            return Ok(vec![(name, 0, self.total_length)]);
+        }
+        let mut start = 0u64;
+        self.files
+            .iter()
+            .map(|file| {
+                let offset = (name.join(file.safe_path()?), start, file.length);
+                start = start
+                    .checked_add(file.length)
+                    .ok_or_else(|| invalid!("total length of files overflows"))?;
+                Ok(offset)
+            })
+            .collect()
+    }
+
+    /// A reader over file `index`'s bytes, given `src` reading the whole
+    /// concatenated content, as a single downloaded blob would hold it.
+    pub fn open_file<'r, R: Read + Seek>(
+        &self,
+        src: &'r mut R,
+        index: usize,
+    ) -> Result<impl Read + 'r> {
+        let offsets = self.file_offsets()?;
+        let (_, start, length) = offsets.get(index).ok_or_else(|| {
+            invalid!(
+                "File {} is out of range, there are {} files",
+                index,
+                offsets.len()
+            )
+        })?;
+        src.seek(SeekFrom::Start(*start))?;
+        Ok(src.take(*length))
+    }
+
+    /// Describes what's unusual about `piece_length`, if anything.  Clients
+    /// expect a power of two between 16 KiB and 16 MiB, but anything else is
+    /// still parseable, so this is a warning rather than an error.
+    pub fn check_piece_length(&self) -> Option<String> {
+        if !self.piece_length.is_power_of_two() {
+            Some(format!(
+                "piece length {} is not a power of two",
+                self.piece_length
+            ))
+        } else if !(MIN_PIECE_LENGTH..=MAX_PIECE_LENGTH).contains(&self.piece_length) {
+            Some(format!(
+                "piece length {} is outside the usual {}..={} range",
+                self.piece_length, MIN_PIECE_LENGTH, MAX_PIECE_LENGTH
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The files piece `index` covers, each with the byte range of that file
+    /// that falls within the piece.
+    pub fn files_for_piece(&self, index: usize) -> Result<Vec<(PathBuf, Range<u64>)>> {
+        if index >= self.piece_count() {
+            return Err(invalid!(
+                "Piece {} is out of range, there are {} pieces",
+                index,
+                self.piece_count()
+            ));
+        }
+        let piece_length = u64::from(self.piece_length);
+        let piece_start = index as u64 * piece_length;
+        let piece_end = piece_start
+            .saturating_add(piece_length)
+            .min(self.total_length);
+        Ok(self
+            .file_offsets()?
+            .into_iter()
+            .filter_map(|(path, start, length)| {
+                let from = piece_start.max(start);
+                let to = piece_end.min(start + length);
+                if from < to {
+                    Some((path, from - start..to - start))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Each piece's SHA-1 hash, borrowed straight from the `pieces` string.
+    pub fn piece_hashes(&self) -> impl Iterator<Item = &'a [u8; PIECE_HASH_LEN]> {
+        self.pieces
+            .chunks_exact(PIECE_HASH_LEN)
+            .map(|hash| <&[u8; PIECE_HASH_LEN]>::try_from(hash).expect("chunks are exact"))
+    }
+
+    /// Each piece's SHA-1 hash, copied out.  Prefer `piece_hashes` for
+    /// torrents with many pieces.
+    pub fn pieces(&self) -> Vec<[u8; PIECE_HASH_LEN]> {
+        self.piece_hashes().copied().collect()
+    }
+
+    /// The number of pieces implied by `total_length` and `piece_length`.
+    pub fn piece_count(&self) -> usize {
+        let piece_length = u64::from(self.piece_length);
+        self.total_length.div_ceil(piece_length) as usize
+    }
+
+    /// Errors if the `pieces` string doesn't hold exactly one hash per piece.
+    pub fn check_piece_count(&self) -> Result<()> {
+        if self.version == TorrentVersion::V2 {
+            return Ok(());
+        }
+        let expected = self.piece_count();
+        let found = self.pieces.len() / PIECE_HASH_LEN;
+        if found != expected {
+            return Err(invalid!(
+                "Expected {} pieces for {} bytes with piece length {}, found {}",
+                expected,
+                self.total_length,
+                self.piece_length,
+                found
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Debug for InfoMetadata<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InfoMetadata")
+            .field("piece_length", &self.piece_length)
+            .field("pieces", &(self.pieces.len() / PIECE_HASH_LEN))
+            .field("total_length", &self.total_length)
+            .field("files", &self.files.len())
+            .finish()
+    }
+}
+
+/// Joins the `info` dictionary back together from the pieces a BEP-9
+/// `ut_metadata` exchange delivers, given the `metadata_size` the peer
+/// announced.  Every piece but the last has to be 16 KiB.  The result is
+/// ready for `Metadata::from_info_bytes`; check its info-hash before
+/// trusting it.
+pub fn assemble_from_pieces(pieces: &[Vec<u8>], total_size: usize) -> Result<Vec<u8>> {
+    let expected_pieces = total_size.div_ceil(METADATA_PIECE_LEN);
+    if pieces.len() != expected_pieces {
+        return Err(invalid!(
+            "got {} metadata pieces, expected {} for {} bytes",
+            pieces.len(),
+            expected_pieces,
+            total_size
+        ));
+    }
+    let mut info = Vec::with_capacity(total_size);
+    for (index, piece) in pieces.iter().enumerate() {
+        let expected_len = METADATA_PIECE_LEN.min(total_size - index * METADATA_PIECE_LEN);
+        if piece.len() != expected_len {
+            return Err(invalid!(
+                "metadata piece {} is {} bytes, expected {}",
+                index,
+                piece.len(),
+                expected_len
+            ));
+        }
+        info.extend_from_slice(piece);
+    }
+    Ok(info)
+}
+
+/// `component` as one plain path component, refusing anything `safe_path`
+/// would.
+fn safe_component(component: &[u8]) -> Result<String> {
+    let component = String::from_utf8_lossy(component);
+    let mut parsed = Path::new(component.as_ref()).components();
+    match (parsed.next(), parsed.next()) {
+        (Some(Component::Normal(x)), None)
+            if x == component.as_ref() && !component.contains('\\') =>
+        {
+            Ok(component.into_owned())
+        }
+        _ => Err(invalid!("Unsafe path component {:?}", component)),
+    }
+}
+
+/// Reads `key` from `dict` as a string, if it's there at all.
+fn optional_str<'a>(dict: &BEncodedType<'a>, key: &str) -> Result<Option<&'a str>> {
+    dict.dict_get_opt(key)?
+        .map(|value| value.as_str())
+        .transpose()
+}
+
+/// Reads `key` from `dict` as an integer, if it's there at all.
+fn optional_int(dict: &BEncodedType, key: &str) -> Result<Option<i64>> {
+    dict.dict_get_opt(key)?
+        .map(|value| value.as_int())
+        .transpose()
+}
+
+/// Looks up `key`, preferring the `{key}.utf-8` variant that older clients
+/// wrote alongside it when that variant is present and really is UTF-8.
+fn preferring_utf8<'a, 'b>(dict: &'b BEncodedType<'a>, key: &str) -> Result<&'b BEncodedType<'a>> {
+    match dict.dict_get_opt(&format!("{}.utf-8", key))? {
+        Some(value) if is_utf8(value) => Ok(value),
+        _ => dict.dict_get(key),
+    }
+}
+
+/// Whether `value` is a string, or list of strings, that are all UTF-8.
+fn is_utf8(value: &BEncodedType) -> bool {
+    match value {
+        BEncodedType::String(x) => str::from_utf8(x).is_ok(),
+        BEncodedType::List(x) => x.iter().all(is_utf8),
+        _ => false,
+    }
+}
+
+/// Flattens a v2 `file tree` into `files`.  Each level is keyed by path
+/// component, and a file's node holds its details under the empty key.
+fn parse_file_tree<'a>(
+    tree: &BEncodedType<'a>,
+    path: &mut Vec<&'a [u8]>,
+    files: &mut Vec<FileEntry<'a>>,
+) -> Result<()> {
+    let entries = match tree {
+        BEncodedType::Dictionary(entries) => entries,
+        _ => return Err(tree.wrong_type("a file tree")),
+    };
+    for (component, node) in entries {
+        if component.is_empty() {
+            if !path.is_empty() {
+                files.push(FileEntry {
+                    length: file_length(node.dict_get("length")?)?,
+                    path: path.clone(),
+                    md5sum: None,
+                });
+            }
+            continue;
+        }
+        path.push(component);
+        parse_file_tree(node, path, files)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Tells v1, v2 and hybrid torrents apart by `meta version` and whether
+/// v1 `pieces` are present too.
+fn parse_version(info: &BEncodedType) -> Result<TorrentVersion> {
+    let meta_version = match info.dict_get_opt("meta version")? {
+        Some(meta_version) => meta_version.as_int()?,
+        None => return Ok(TorrentVersion::V1),
+    };
+    match meta_version {
+        1 => Ok(TorrentVersion::V1),
+        2 if info.dict_get_opt("pieces")?.is_some() => Ok(TorrentVersion::Hybrid),
+        2 => Ok(TorrentVersion::V2),
+        _ => Err(invalid!("Unsupported meta version {}", meta_version)),
+    }
+}
+
+fn parse_announce_list<'a>(root: &BEncodedType<'a>) -> Result<Vec<Vec<Cow<'a, str>>>> {
+    let tiers = match root.dict_get_opt("announce-list")? {
+        Some(tiers) => tiers.as_list()?,
+        None => return Ok(Vec::new()),
+    };
+    tiers
+        .iter()
+        .map(|tier| {
+            tier.as_list()?
+                .iter()
+                .map(|url| url.as_str().map(Cow::Borrowed))
+                .collect()
+        })
+        .collect()
+}
+
+/// Reads the `nodes` list, where each node is a `[host, port]` pair.
+fn parse_nodes<'a>(root: &BEncodedType<'a>) -> Result<Vec<(&'a str, u16)>> {
+    let nodes = match root.dict_get_opt("nodes")? {
+        Some(nodes) => nodes.as_list()?,
+        None => return Ok(Vec::new()),
+    };
+    nodes
+        .iter()
+        .map(|node| match node.as_list()? {
+            [host, port] => {
+                let port = port.as_int()?;
+                let port = u16::try_from(port)
+                    .map_err(|_| invalid!("DHT node port {} is out of range", port))?;
+                Ok((host.as_str()?, port))
+            }
+            items => Err(invalid!(
+                "DHT node has {} items, expected a host and a port",
+                items.len()
+            )),
+        })
+        .collect()
+}
+
+/// Decodes an `md5sum`, which is 32 hex digits.
+fn parse_md5sum(hex: &[u8]) -> Result<[u8; 16]> {
+    if hex.len() != 32 {
+        return Err(invalid!(
+            "md5sum is {} characters long, expected 32",
+            hex.len()
+        ));
+    }
+    let mut md5sum = [0; 16];
+    for (byte, digits) in md5sum.iter_mut().zip(hex.chunks_exact(2)) {
+        // `from_str_radix` would also take a sign, like `+a`.
+        *byte = Some(digits)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| invalid!("md5sum {:?} is not hex", String::from_utf8_lossy(hex)))?;
+    }
+    Ok(md5sum)
+}
+
+fn file_length(length: &BEncodedType) -> Result<u64> {
+    let length = length.as_int()?;
+    u64::try_from(length).map_err(|_| invalid!("file length {} is out of range", length))
+}
+
+/// The files' lengths added up, failing rather than wrapping if that
+/// doesn't fit in a u64.
+fn total_length(files: &[FileEntry]) -> Result<u64> {
+    files.iter().try_fold(0u64, |total, file| {
+        total
+            .checked_add(file.length)
+            .ok_or_else(|| invalid!("total length of files overflows"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha1::sha1;
+    use crate::sha256::sha256;
+
+    fn single_file_torrent(length: u64, piece_length: u32, pieces_len: usize) -> Vec<u8> {
+        let mut buf = format!(
+            "d8:announce17:http://t/announce4:infod6:lengthi{}e4:name3:foo12:piece lengthi{}e6:pieces{}:",
+            length, piece_length, pieces_len
+        )
+        .into_bytes();
+        buf.extend(vec![0xAB; pieces_len]);
+        buf.extend_from_slice(b"ee");
+        buf
+    }
+
+    /// A torrent holding one file per `(length, path)`, with enough piece
+    /// hashes for `piece_length`.
+    fn multi_file_torrent(files: &[(u64, &str)], piece_length: u32) -> Vec<u8> {
+        let mut buf = b"d8:announce1:a4:infod5:filesl".to_vec();
+        for (length, path) in files {
+            buf.extend(format!("d6:lengthi{}e4:pathl{}:{}ee", length, path.len(), path).bytes());
+        }
+        let total: u64 = files.iter().map(|(length, _)| length).sum();
+        let pieces_len = total.div_ceil(u64::from(piece_length)) as usize * PIECE_HASH_LEN;
+        buf.extend(
+            format!(
+                "e4:name3:foo12:piece lengthi{}e6:pieces{}:",
+                piece_length, pieces_len
+            )
+            .bytes(),
+        );
+        buf.extend(vec![0xAB; pieces_len]);
+        buf.extend_from_slice(b"ee");
+        buf
+    }
+
+    /// Appends already-encoded `entries` to the top level dictionary.
+    fn with_top_level(mut torrent: Vec<u8>, entries: &[u8]) -> Vec<u8> {
+        assert_eq!(torrent.pop(), Some(b'e'));
+        torrent.extend_from_slice(entries);
+        torrent.push(b'e');
+        torrent
+    }
+
+    mod piece_count {
+        use super::*;
+
+        #[test]
+        fn rounds_up_partial_piece() {
+            let buf = single_file_torrent(10, 4, 60);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.piece_count(), 3);
+        }
+
+        #[test]
+        fn consistent_pieces_pass() {
+            let buf = single_file_torrent(8, 4, 40);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert!(meta.info.check_piece_count().is_ok());
+        }
+
+        #[test]
+        fn truncated_pieces_fail() {
+            let buf = single_file_torrent(10, 4, 40);
+            let meta = Metadata::parse(&buf).unwrap();
+            let err = meta.info.check_piece_count().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Expected 3 pieces for 10 bytes with piece length 4, found 2"
+            );
+        }
+
+        #[test]
+        fn multi_file_lengths_are_summed() {
+            let mut buf = b"d8:announce1:a4:infod5:filesl".to_vec();
+            buf.extend_from_slice(b"d6:lengthi5e4:pathl1:aee");
+            buf.extend_from_slice(b"d6:lengthi6e4:pathl1:bee");
+            buf.extend_from_slice(b"e4:name3:foo12:piece lengthi4e6:pieces60:");
+            buf.extend_from_slice(&[0xAB; 60]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.piece_count(), 3);
+        }
+    }
+
+    mod num_pieces {
+        use super::*;
+
+        #[test]
+        fn metadata_matches_info_dict() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let pieces = meta.root().get_bytes(&["info", "pieces"]).unwrap();
+            assert_eq!(meta.num_pieces(), pieces.len() / PIECE_HASH_LEN);
+            assert_eq!(meta.num_pieces(), 3);
+        }
+    }
+
+    mod piece_hashes {
+        use super::*;
+
+        #[test]
+        fn borrows_each_hash() {
+            let mut buf = b"d8:announce1:a4:infod6:lengthi12e4:name3:foo".to_vec();
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces60:");
+            let pieces: Vec<u8> = (0..60).collect();
+            buf.extend_from_slice(&pieces);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.piece_hashes().count(), 3);
+            assert_eq!(&meta.info.piece_hashes().next().unwrap()[..], &pieces[..20]);
+            assert_eq!(meta.info.pieces()[2][..], pieces[40..]);
+        }
+    }
+
+    mod file_offsets {
+        use super::*;
+
+        #[test]
+        fn offsets_are_cumulative() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b"), (7, "c")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.file_offsets().unwrap(),
+                vec![
+                    (PathBuf::from("foo/a"), 0, 5),
+                    (PathBuf::from("foo/b"), 5, 6),
+                    (PathBuf::from("foo/c"), 11, 7),
+                ]
+            );
+        }
+
+        #[test]
+        fn single_file() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.file_offsets().unwrap(),
+                vec![(PathBuf::from("foo"), 0, 4)]
+            );
+        }
+
+        #[test]
+        fn rejects_parent_components() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "..")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let err = meta.info.file_offsets().unwrap_err();
+            assert_eq!(err.to_string(), "Unsafe path component \"..\"");
+            assert!(meta.files().is_err());
+        }
+
+        #[test]
+        fn rejects_unsafe_name() {
+            for name in [&b"2:.."[..], b"4:/etc", b"3:a/b", b"0:"] {
+                let mut buf = b"d8:announce1:a4:infod6:lengthi4e4:name".to_vec();
+                buf.extend_from_slice(name);
+                buf.extend_from_slice(b"12:piece lengthi4e6:pieces20:");
+                buf.extend_from_slice(&[0xAB; 20]);
+                buf.extend_from_slice(b"ee");
+                let meta = Metadata::parse(&buf).unwrap();
+                assert!(meta.info.file_offsets().is_err(), "{:?}", name);
+                assert!(meta.files().is_err(), "{:?}", name);
+            }
+        }
+    }
+
+    mod files {
+        use super::*;
+
+        #[test]
+        fn single_file() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            let files: Vec<FileInfo> = meta.files().unwrap().collect();
+            assert_eq!(
+                files,
+                vec![FileInfo {
+                    path: PathBuf::from("foo"),
+                    length: 4
+                }]
+            );
+        }
+
+        #[test]
+        fn multi_file_in_order() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b"), (7, "c")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let files: Vec<(PathBuf, u64)> =
+                meta.files().unwrap().map(|f| (f.path, f.length)).collect();
+            assert_eq!(
+                files,
+                vec![
+                    (PathBuf::from("foo/a"), 5),
+                    (PathBuf::from("foo/b"), 6),
+                    (PathBuf::from("foo/c"), 7),
+                ]
+            );
+        }
+    }
+
+    mod open_file {
+        use super::*;
+        use std::io::Cursor;
+
+        #[test]
+        fn middle_file() {
+            let buf = multi_file_torrent(&[(3, "a"), (4, "b"), (2, "c")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let mut content = Cursor::new(b"aaabbbbcc".to_vec());
+            let mut file = Vec::new();
+            meta.info
+                .open_file(&mut content, 1)
+                .unwrap()
+                .read_to_end(&mut file)
+                .unwrap();
+            assert_eq!(file, b"bbbb");
+        }
+
+        #[test]
+        fn out_of_range() {
+            let buf = multi_file_torrent(&[(3, "a"), (4, "b"), (2, "c")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let mut content = Cursor::new(b"aaabbbbcc".to_vec());
+            assert!(meta.info.open_file(&mut content, 3).is_err());
+        }
+    }
+
+    mod files_for_piece {
+        use super::*;
+
+        #[test]
+        fn piece_spans_two_files() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.files_for_piece(1).unwrap(),
+                vec![
+                    (PathBuf::from("foo/a"), 4..5),
+                    (PathBuf::from("foo/b"), 0..3)
+                ]
+            );
+        }
+
+        #[test]
+        fn last_piece_is_short() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.files_for_piece(2).unwrap(),
+                vec![(PathBuf::from("foo/b"), 3..6)]
+            );
+        }
+
+        #[test]
+        fn skips_empty_files() {
+            let buf = multi_file_torrent(&[(4, "a"), (0, "b"), (4, "c")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.files_for_piece(1).unwrap(),
+                vec![(PathBuf::from("foo/c"), 0..4)]
+            );
+        }
+
+        #[test]
+        fn out_of_range() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert!(meta.info.files_for_piece(3).is_err());
+        }
+    }
+
+    mod version {
+        use super::*;
+
+        const V2_TORRENT: &[u8] = b"d8:announce1:a4:infod9:file treede12:meta versioni2e4:name3:foo12:piece lengthi16384eee";
+
+        #[test]
+        fn v1() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.version(), TorrentVersion::V1);
+            assert_eq!(meta.native_info_hash(), InfoHash::Sha1(meta.info_hash()));
+        }
+
+        #[test]
+        fn v2() {
+            let meta = Metadata::parse(V2_TORRENT).unwrap();
+            assert_eq!(meta.version(), TorrentVersion::V2);
+            assert_eq!(
+                meta.native_info_hash(),
+                InfoHash::Sha256(sha256(meta.info_bytes()))
+            );
+        }
+
+        #[test]
+        fn hybrid() {
+            let mut buf = b"d8:announce1:a4:infod9:file treede6:lengthi4e12:meta versioni2e4:name3:foo12:piece lengthi4e6:pieces20:".to_vec();
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.version(), TorrentVersion::Hybrid);
+            assert_eq!(meta.native_info_hash().as_bytes().len(), 32);
+        }
+
+        #[test]
+        fn unknown_meta_version() {
+            let buf = b"d8:announce1:a4:infod9:file treede12:meta versioni3e4:name3:foo12:piece lengthi16384eee";
+            assert!(Metadata::parse(buf).is_err());
+        }
+    }
+
+    mod hash_info_into {
+        use super::*;
+
+        #[test]
+        fn sha1_matches_info_hash() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            let mut hasher = Sha1::new();
+            meta.hash_info_into(&mut hasher).unwrap();
+            assert_eq!(hasher.finish(), meta.info_hash());
+        }
+
+        #[test]
+        fn any_writer() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            let mut copy: Vec<u8> = Vec::new();
+            meta.hash_info_into(&mut copy).unwrap();
+            assert_eq!(copy, meta.info_bytes());
+        }
+    }
+
+    mod assemble_from_pieces {
+        use super::*;
+
+        fn large_info() -> Vec<u8> {
+            let mut info = b"d6:lengthi8000e4:name3:foo12:piece lengthi4e6:pieces40000:".to_vec();
+            info.extend_from_slice(&[0xAB; 40_000]);
+            info.push(b'e');
+            info
+        }
+
+        #[test]
+        fn reassembles_split_info() {
+            let info = large_info();
+            let pieces: Vec<Vec<u8>> = info
+                .chunks(METADATA_PIECE_LEN)
+                .map(<[u8]>::to_vec)
+                .collect();
+            assert_eq!(pieces.len(), 3);
+            let assembled = assemble_from_pieces(&pieces, info.len()).unwrap();
+            assert_eq!(assembled, info);
+            let meta = Metadata::from_info_bytes(&assembled).unwrap();
+            assert_eq!(meta.info_hash(), sha1(&info));
+        }
+
+        #[test]
+        fn wrong_total_size() {
+            let info = large_info();
+            let pieces: Vec<Vec<u8>> = info
+                .chunks(METADATA_PIECE_LEN)
+                .map(<[u8]>::to_vec)
+                .collect();
+            let last_len = pieces[2].len();
+            let err = assemble_from_pieces(&pieces, info.len() + 1).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "metadata piece 2 is {} bytes, expected {}",
+                    last_len,
+                    last_len + 1
+                )
+            );
+            assert!(assemble_from_pieces(&pieces, 4 * METADATA_PIECE_LEN).is_err());
+        }
+
+        #[test]
+        fn short_middle_piece() {
+            let pieces = vec![vec![0; 100], vec![0; 100]];
+            let err = assemble_from_pieces(&pieces, METADATA_PIECE_LEN + 100).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "metadata piece 0 is 100 bytes, expected 16384"
+            );
+        }
+
+        #[test]
+        fn wrong_piece_count() {
+            let pieces = vec![vec![0; 100], vec![0; 100]];
+            let err = assemble_from_pieces(&pieces, 200).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "got 2 metadata pieces, expected 1 for 200 bytes"
+            );
+        }
+    }
+
+    mod info_hash_v2 {
+        use super::*;
+
+        #[test]
+        fn hybrid_has_both_hashes() {
+            let mut buf = b"d8:announce1:a4:infod9:file treed3:food0:d6:lengthi4eeee6:lengthi4e12:meta versioni2e4:name3:foo12:piece lengthi4e6:pieces20:".to_vec();
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info_hash(), sha1(meta.info_bytes()));
+            let v2 = meta.info_hash_v2().unwrap();
+            assert_eq!(v2, sha256(meta.info_bytes()));
+            assert_eq!(meta.info_hash_v2_truncated().unwrap()[..], v2[..20]);
+            assert_ne!(meta.info_hash()[..], v2[..20]);
+        }
+
+        #[test]
+        fn absent_for_v1() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info_hash_v2(), None);
+            assert_eq!(meta.info_hash_v2_truncated(), None);
+        }
+    }
+
+    mod total_length {
+        use super::*;
+
+        #[test]
+        fn overflowing_files() {
+            let file = format!("d6:lengthi{}e4:pathl1:aee", i64::MAX);
+            let buf = format!(
+                "d8:announce1:a4:infod5:filesl{0}{0}{0}e4:name3:foo12:piece lengthi16384e6:pieces0:ee",
+                file
+            );
+            let err = Metadata::parse(buf.as_bytes()).unwrap_err();
+            assert_eq!(err.to_string(), "total length of files overflows");
+        }
+
+        #[test]
+        fn overflowing_file_tree() {
+            let file = format!("d0:d6:lengthi{}eee", i64::MAX);
+            let buf = format!(
+                "d8:announce1:a4:infod9:file treed1:a{0}1:b{0}1:c{0}e12:meta versioni2e4:name3:foo12:piece lengthi16384eee",
+                file
+            );
+            let err = Metadata::parse(buf.as_bytes()).unwrap_err();
+            assert_eq!(err.to_string(), "total length of files overflows");
+        }
+    }
+
+    mod file_tree {
+        use super::*;
+
+        fn v2_torrent(file_tree: &str) -> Vec<u8> {
+            format!(
+                "d8:announce1:a4:infod9:file tree{}12:meta versioni2e4:name3:foo12:piece lengthi16384eee",
+                file_tree
+            )
+            .into_bytes()
+        }
+
+        #[test]
+        fn nested_files() {
+            let buf = v2_torrent(
+                "d3:dird1:ad0:d6:lengthi5eee1:bd0:d6:lengthi7eeee5:x.txtd0:d6:lengthi3eeee",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            let files: Vec<(u64, Vec<&[u8]>)> = meta
+                .info
+                .files()
+                .iter()
+                .map(|file| (file.length, file.path.clone()))
+                .collect();
+            assert_eq!(
+                files,
+                vec![
+                    (5, vec![&b"dir"[..], b"a"]),
+                    (7, vec![&b"dir"[..], b"b"]),
+                    (3, vec![&b"x.txt"[..]]),
+                ]
+            );
+            assert_eq!(meta.info.piece_count(), 1);
+        }
+
+        #[test]
+        fn single_file() {
+            let buf = v2_torrent("d3:food0:d6:lengthi40000eeee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert!(meta.info.files().is_empty());
+            assert_eq!(meta.info.piece_count(), 3);
+        }
+
+        #[test]
+        fn file_without_length() {
+            let buf = v2_torrent("d3:food0:deee");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+    }
+
+    mod publisher {
+        use super::*;
+
+        #[test]
+        fn present() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"9:publisher5:alice13:publisher-url15:http://alice/me",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.publisher, Some("alice"));
+            assert_eq!(meta.publisher_url, Some("http://alice/me"));
+        }
+
+        #[test]
+        fn absent() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.publisher, None);
+            assert_eq!(meta.publisher_url, None);
+        }
+    }
+
+    mod sanity_check {
+        use super::*;
+
+        #[test]
+        fn flawed_torrent() {
+            let mut buf = b"d4:infod5:filesld6:lengthi0e4:pathl3:dir5:emptyee".to_vec();
+            buf.extend_from_slice(b"d6:lengthi5e4:pathl1:aeee4:name3:foo");
+            buf.extend_from_slice(b"12:piece lengthi3e6:pieces40:");
+            buf.extend_from_slice(&[0xAB; 40]);
+            buf.extend_from_slice(b"e5:nodesll9:127.0.0.1i6881eeee");
+            let meta = Metadata::parse(&buf).unwrap();
+            let messages: Vec<_> = meta.sanity_check().iter().map(|x| x.to_string()).collect();
+            assert_eq!(
+                messages,
+                vec![
+                    "warning: piece length 3 is not a power of two",
+                    "warning: torrent has no trackers, only DHT nodes",
+                    "warning: file dir/empty is empty",
+                    "info: torrent has no creation date",
+                ]
+            );
+        }
+
+        #[test]
+        fn piece_count_mismatch_is_an_error() {
+            let buf = with_top_level(
+                single_file_torrent(40000, 16384, 20),
+                b"13:creation datei1600000000e",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            let diagnostics = meta.sanity_check();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+            assert_eq!(
+                diagnostics[0].to_string(),
+                "error: Expected 3 pieces for 40000 bytes with piece length 16384, found 1"
+            );
+        }
+
+        #[test]
+        fn clean_torrent() {
+            let buf = with_top_level(
+                single_file_torrent(16384, 16384, 20),
+                b"13:creation datei1600000000e",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.sanity_check(), vec![]);
+        }
+
+        #[test]
+        fn no_trackers_or_nodes() {
+            let buf = single_file_torrent(16384, 16384, 20);
+            let info = Metadata::parse(&buf).unwrap().info_bytes().to_vec();
+            let meta = Metadata::from_info_bytes(&info).unwrap();
+            let messages: Vec<_> = meta.sanity_check().iter().map(|x| x.to_string()).collect();
+            assert!(
+                messages.contains(&"warning: torrent has no trackers or DHT nodes".to_string()),
+                "{:?}",
+                messages
+            );
+        }
+    }
+
+    mod creation_datetime {
+        use super::*;
+
+        #[test]
+        fn known_timestamp() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:creation datei1600000000e",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.creation_date, Some(1_600_000_000));
+            let date = meta.creation_datetime().unwrap();
+            assert_eq!((date.year, date.month, date.day), (2020, 9, 13));
+        }
+
+        #[test]
+        fn absent() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.creation_date, None);
+            assert_eq!(meta.creation_datetime(), None);
+        }
+
+        #[test]
+        fn out_of_range() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:creation datei9223372036854775807e",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.creation_date, Some(i64::MAX));
+            assert_eq!(meta.creation_datetime(), None);
+        }
+
+        #[test]
+        fn wrong_type() {
+            let buf = with_top_level(single_file_torrent(4, 4, 20), b"13:creation date3:now");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+    }
+
+    mod announce {
+        use super::*;
+
+        /// A torrent with no top level keys besides `info`.
+        fn bare_torrent() -> Vec<u8> {
+            let mut buf = b"d4:infod6:lengthi4e4:name3:foo12:piece lengthi4e6:pieces20:".to_vec();
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            buf
+        }
+
+        #[test]
+        fn announce_only() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.announce, Some("http://t/announce"));
+            assert!(meta.announce_list.is_empty());
+        }
+
+        #[test]
+        fn announce_list_only() {
+            let buf = with_top_level(bare_torrent(), b"13:announce-listll3:udpel4:http5:httpsee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.announce, None);
+            assert_eq!(meta.announce_list, vec![vec!["udp"], vec!["http", "https"]]);
+        }
+
+        #[test]
+        fn dht_only() {
+            let buf = with_top_level(bare_torrent(), b"5:nodesll9:127.0.0.1i6881eee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.announce, None);
+        }
+
+        #[test]
+        fn no_peer_source_fails() {
+            let buf = bare_torrent();
+            assert!(Metadata::parse(&buf).is_err());
+        }
+    }
+
+    mod to_bencoded {
+        use super::*;
+        use crate::bencode::decode_lenient;
+
+        #[test]
+        fn unchanged_round_trips() {
+            let buf = with_top_level(single_file_torrent(4, 4, 20), b"7:comment2:hi");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                Metadata::parse(&meta.to_bencoded()).unwrap().root(),
+                meta.root()
+            );
+        }
+
+        #[test]
+        fn edited_trackers_keep_the_info_hash() {
+            // Unsorted `info` keys would come out sorted if it were re-encoded.
+            let mut buf = b"d8:announce1:a4:infod4:name3:foo6:lengthi4e".to_vec();
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.merge_trackers(&["udp://b".to_string()]);
+
+            let out = meta.to_bencoded();
+            let edited = Metadata::parse(&out).unwrap();
+            assert_eq!(edited.info_bytes(), meta.info_bytes());
+            assert_eq!(edited.info_hash(), meta.info_hash());
+            assert_eq!(edited.announce, Some("a"));
+            assert_eq!(edited.announce_list, vec![vec!["a"], vec!["udp://b"]]);
+        }
+
+        #[test]
+        fn repeated_root_key_keeps_the_last() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"7:comment5:first7:comment4:last",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            let out = meta.to_bencoded();
+            let (reparsed, warnings) = decode_lenient(&out).unwrap();
+            assert!(warnings.is_empty(), "{:?}", warnings);
+            assert_eq!(
+                reparsed.dict_get("comment").unwrap(),
+                &BEncodedType::string("last")
+            );
+            let comments = reparsed
+                .as_dict()
+                .unwrap()
+                .iter()
+                .filter(|(key, _)| *key == b"comment")
+                .count();
+            assert_eq!(comments, 1);
+        }
+    }
+
+    mod announce_urls {
+        use super::*;
+
+        #[test]
+        fn dedups_in_first_seen_order() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:announce-listll3:udp17:http://t/announceel3:udp5:httpsee",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.announce_urls(),
+                vec!["http://t/announce", "udp", "https"]
+            );
+        }
+
+        #[test]
+        fn announce_only() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.announce_urls(), vec!["http://t/announce"]);
+        }
+    }
+
+    mod tracker_tiers {
+        use super::*;
+
+        #[test]
+        fn two_tiers() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:announce-listll3:udp17:http://t/announceel3:udp5:httpsee",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.tracker_tiers(),
+                vec![vec!["udp", "http://t/announce"], vec!["udp", "https"]]
+            );
+        }
+
+        #[test]
+        fn announce_only() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.tracker_tiers(), vec![vec!["http://t/announce"]]);
+        }
+    }
+
+    mod nodes {
+        use super::*;
+
+        #[test]
+        fn two_bootstrap_nodes() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"5:nodesll9:127.0.0.1i6881eel8:dht.hosti6882eee",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.nodes(),
+                vec![
+                    ("127.0.0.1".to_string(), 6881),
+                    ("dht.host".to_string(), 6882)
+                ]
+            );
+        }
+
+        #[test]
+        fn absent() {
+            let buf = single_file_torrent(4, 4, 20);
+            assert!(Metadata::parse(&buf).unwrap().nodes().is_empty());
+        }
+
+        #[test]
+        fn port_out_of_range() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"5:nodesll9:127.0.0.1i65536eee",
+            );
+            assert!(Metadata::parse(&buf).is_err());
+        }
+
+        #[test]
+        fn wrong_item_count() {
+            let buf = with_top_level(single_file_torrent(4, 4, 20), b"5:nodesll9:127.0.0.1ee");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+    }
+
+    mod add_tracker {
+        use super::*;
+
+        #[test]
+        fn survives_a_round_trip() {
+            let buf = single_file_torrent(4, 4, 20);
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.add_tracker("udp://mine");
+            let out = meta.to_bencoded();
+            let edited = Metadata::parse(&out).unwrap();
+            assert_eq!(
+                edited.announce_list,
+                vec![vec!["http://t/announce", "udp://mine"]]
+            );
+            assert_eq!(
+                edited.info_hash(),
+                Metadata::parse(&buf).unwrap().info_hash()
+            );
+        }
+
+        #[test]
+        fn joins_the_first_tier() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:announce-listll1:ael1:bee",
+            );
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.add_tracker("c");
+            assert_eq!(meta.announce_list, vec![vec!["a", "c"], vec!["b"]]);
+        }
+
+        #[test]
+        fn skips_known_trackers() {
+            let buf = with_top_level(
+                single_file_torrent(4, 4, 20),
+                b"13:announce-listll1:ael1:bee",
+            );
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.add_tracker("b");
+            meta.add_tracker("http://t/announce");
+            assert_eq!(meta.announce_list, vec![vec!["a"], vec!["b"]]);
+        }
+    }
+
+    mod merge_trackers {
+        use super::*;
+
+        fn magnet_trackers() -> Vec<String> {
+            // The `tr` parameters of a magnet link for the same torrent.
+            vec![
+                "http://t/announce".to_string(),
+                "udp://tracker:1337".to_string(),
+                "udp://tracker:1337".to_string(),
+                "http://other/announce".to_string(),
+            ]
+        }
+
+        #[test]
+        fn appends_new_trackers_as_a_tier() {
+            let buf = single_file_torrent(4, 4, 20);
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.merge_trackers(&magnet_trackers());
+            assert_eq!(
+                meta.announce_list,
+                vec![
+                    vec!["http://t/announce"],
+                    vec!["udp://tracker:1337", "http://other/announce"],
+                ]
+            );
+        }
+
+        #[test]
+        fn nothing_new() {
+            let buf = single_file_torrent(4, 4, 20);
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.merge_trackers(&["http://t/announce".to_string()]);
+            assert_eq!(meta.announce_list, vec![vec!["http://t/announce"]]);
+        }
+
+        #[test]
+        fn keeps_existing_tiers() {
+            let torrent = single_file_torrent(4, 4, 20);
+            let buf = with_top_level(torrent, b"13:announce-listll18:udp://tracker:1337ee");
+            let mut meta = Metadata::parse(&buf).unwrap();
+            meta.merge_trackers(&magnet_trackers());
+            assert_eq!(
+                meta.announce_list,
+                vec![vec!["udp://tracker:1337"], vec!["http://other/announce"]]
+            );
+        }
+    }
+
+    mod same_content {
+        use super::*;
+
+        #[test]
+        fn ignores_top_level_keys() {
+            let plain = single_file_torrent(4, 4, 20);
+            let published = with_top_level(plain.clone(), b"9:publisher3:bob");
+            assert!(Metadata::parse(&plain)
+                .unwrap()
+                .same_content(&Metadata::parse(&published).unwrap()));
+        }
+
+        #[test]
+        fn different_info() {
+            let a = single_file_torrent(4, 4, 20);
+            let b = single_file_torrent(8, 8, 20);
+            assert!(!Metadata::parse(&a)
+                .unwrap()
+                .same_content(&Metadata::parse(&b).unwrap()));
+        }
+    }
+
+    mod fingerprint {
+        use super::*;
+
+        #[test]
+        fn ignores_announce() {
+            let a = single_file_torrent(40000, 16384, 60);
+            let meta_a = Metadata::parse(&a).unwrap();
+            let mut b = b"d8:announce17:udp://other:1337/4:info".to_vec();
+            b.extend_from_slice(meta_a.info_bytes());
+            b.push(b'e');
+            let meta_b = Metadata::parse(&b).unwrap();
+            assert_ne!(meta_a.announce, meta_b.announce);
+            assert_eq!(meta_a.fingerprint(), meta_b.fingerprint());
+        }
+
+        #[test]
+        fn format() {
+            let buf = single_file_torrent(40000, 16384, 60);
+            let meta = Metadata::parse(&buf).unwrap();
+            let hash = to_hex(&meta.info_hash());
+            assert_eq!(meta.fingerprint(), format!("{}-40000", &hash[..12]));
+        }
+    }
+
+    mod info_bytes {
+        use super::*;
+
+        #[test]
+        fn spans_info_dictionary() {
+            let buf = with_top_level(single_file_torrent(8, 4, 40), b"7:comment2:hi");
+            let meta = Metadata::parse(&buf).unwrap();
+            let info_bytes = meta.info_bytes();
+            assert_eq!(info_bytes.first(), Some(&b'd'));
+            assert_eq!(info_bytes.last(), Some(&b'e'));
+            assert_eq!(
+                &decode(info_bytes).unwrap(),
+                meta.root().dict_get("info").unwrap()
+            );
+        }
+
+        #[test]
+        fn repeated_info_uses_the_last() {
+            let mut buf =
+                b"d8:announce1:a4:infod6:lengthi4e4:name3:foo12:piece lengthi4e6:pieces20:"
+                    .to_vec();
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"e4:infod6:lengthi4e4:name3:bar12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xCD; 20]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.name_lossy(), "bar");
+            assert_eq!(
+                &decode(meta.info_bytes()).unwrap(),
+                meta.root().dict_get("info").unwrap()
+            );
+            assert_eq!(meta.info_hash(), sha1(meta.info_bytes()));
+            assert!(meta.info_bytes().windows(11).any(|x| x == b"4:name3:bar"));
+        }
+    }
+
+    mod info_document {
+        use super::*;
+
+        #[test]
+        fn re_encodes_to_info_bytes() {
+            let buf = with_top_level(single_file_torrent(8, 4, 40), b"7:comment2:hi");
+            let meta = Metadata::parse(&buf).unwrap();
+            let info = meta.info_document();
+            assert_eq!(info.dict_get("length").unwrap().as_int().unwrap(), 8);
+            let encoded = encode(&info);
+            assert_eq!(encoded, meta.info_bytes());
+            assert_eq!(sha1(&encoded), meta.info_hash());
+        }
+    }
+
+    mod from_info_bytes {
+        use super::*;
+
+        #[test]
+        fn round_trips_standalone_info() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let encoded = encode(&meta.info_document());
+            let standalone = Metadata::from_info_bytes(&encoded).unwrap();
+            assert_eq!(standalone.info_hash(), meta.info_hash());
+            assert_eq!(standalone.info.files().len(), 2);
+            assert_eq!(standalone.name_utf8().unwrap(), "foo");
+            assert!(standalone.announce_urls().is_empty());
+            assert_eq!(
+                Metadata::parse(&standalone.to_bencoded())
+                    .unwrap_err()
+                    .to_string(),
+                "Torrent has no announce, announce-list or DHT nodes"
+            );
+        }
+
+        #[test]
+        fn rejects_non_dictionary() {
+            assert!(Metadata::from_info_bytes(b"le").is_err());
+            assert!(Metadata::from_info_bytes(b"d4:name3:fooe").is_err());
+        }
+    }
+
+    mod piece_length {
+        use super::*;
+
+        #[test]
+        fn metadata_matches_info_dict() {
+            let buf = multi_file_torrent(&[(5, "a"), (6, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            let from_dict = meta.root().get_i64(&["info", "piece length"]).unwrap();
+            assert_eq!(i64::from(meta.piece_length()), from_dict);
+            assert_eq!(meta.piece_length(), meta.info.piece_length());
+        }
+
+        #[test]
+        fn power_of_two() {
+            let buf = single_file_torrent(262144, 262144, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.piece_length(), 262144);
+            assert_eq!(meta.info.check_piece_length(), None);
+        }
+
+        #[test]
+        fn not_a_power_of_two() {
+            let buf = single_file_torrent(100000, 100000, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.piece_length(), 100000);
+            assert!(meta.info.check_piece_length().is_some());
+        }
+
+        #[test]
+        fn too_small() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert!(meta.info.check_piece_length().is_some());
+        }
+
+        #[test]
+        fn zero() {
+            let buf = single_file_torrent(0, 0, 0);
+            let err = Metadata::parse(&buf).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Invalid piece length: must be greater than 0"
+            );
+        }
+
+        #[test]
+        fn negative() {
+            let buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:foo12:piece lengthi-4e6:pieces0:ee";
+            let err = Metadata::parse(buf).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Invalid piece length: integer -4 is not a valid u32"
+            );
+        }
+    }
+
+    mod file_length {
+        use super::*;
+
+        #[test]
+        fn negative() {
+            let mut buf = b"d8:announce1:a4:infod5:filesld6:lengthi-5e4:pathl1:aee".to_vec();
+            buf.extend_from_slice(b"e4:name3:foo12:piece lengthi4e6:pieces0:ee");
+            let err = Metadata::parse(&buf).unwrap_err();
+            assert_eq!(err.to_string(), "file length -5 is out of range");
+        }
+    }
+
+    mod top_level {
+        use super::*;
+
+        #[test]
+        fn integer() {
+            let err = Metadata::parse(b"i42e").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "top-level value must be a dictionary, found integer"
+            );
+        }
+
+        #[test]
+        fn list() {
+            let err = Metadata::parse(b"ld4:infodeee").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "top-level value must be a dictionary, found list"
+            );
+        }
+    }
+
+    mod length_or_files {
+        use super::*;
+
+        #[test]
+        fn single_file() {
+            let buf = single_file_torrent(8, 4, 40);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert!(meta.info.files().is_empty());
+            assert_eq!(meta.info.total_length(), 8);
+        }
+
+        #[test]
+        fn multi_file() {
+            let buf = multi_file_torrent(&[(5, "a"), (3, "b")], 4);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.files().len(), 2);
+            assert_eq!(meta.info.total_length(), 8);
+        }
+
+        #[test]
+        fn both() {
+            let mut buf = b"d8:announce1:a4:infod5:filesld6:lengthi4e4:pathl1:aee".to_vec();
+            buf.extend_from_slice(b"e6:lengthi4e4:name3:foo12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let err = Metadata::parse(&buf).unwrap_err();
+            assert_eq!(err.to_string(), "torrent declares both length and files");
+        }
+
+        #[test]
+        fn neither() {
+            let buf = b"d8:announce1:a4:infod4:name3:foo12:piece lengthi4e6:pieces0:ee";
+            let err = Metadata::parse(buf).unwrap_err();
+            assert_eq!(err.to_string(), "torrent declares neither length nor files");
+        }
+    }
+
+    mod name {
+        use super::*;
+
+        #[test]
+        fn utf8_name() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.name_bytes(), b"foo");
+            assert_eq!(meta.name_lossy(), "foo");
+            assert_eq!(meta.name_utf8().unwrap(), "foo");
+        }
+
+        #[test]
+        fn invalid_utf8_name() {
+            let mut buf = b"d8:announce1:a4:infod6:lengthi4e4:name3:f\xffo".to_vec();
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.name_bytes(), b"f\xffo");
+            assert_eq!(meta.name_lossy(), "f\u{FFFD}o");
+            assert!(meta.name_utf8().is_err());
+        }
+    }
+
+    mod display_name {
+        use super::*;
+
+        #[test]
+        fn named() {
+            let buf = single_file_torrent(4, 4, 20);
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.display_name(), "foo");
+        }
+
+        #[test]
+        fn nameless_falls_back_to_info_hash() {
+            let mut buf = b"d8:announce1:a4:infod6:lengthi4e4:name0:".to_vec();
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.display_name(), to_hex(&meta.info_hash()));
+        }
+
+        fn nameless_info() -> Vec<u8> {
+            let mut buf = b"d6:lengthi4e4:name0:12:piece lengthi4e6:pieces20:".to_vec();
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.push(b'e');
+            buf
+        }
+
+        #[test]
+        fn nameless_magnet_falls_back_to_dn() {
+            let buf = nameless_info();
+            let meta = Metadata::from_magnet(&buf, Some("from the link"), &[]).unwrap();
+            assert_eq!(meta.display_name(), "from the link");
+        }
+
+        #[test]
+        fn info_name_beats_dn() {
+            let buf = single_file_torrent(4, 4, 20);
+            let info = Metadata::parse(&buf).unwrap().info_bytes().to_vec();
+            let meta = Metadata::from_magnet(&info, Some("from the link"), &[]).unwrap();
+            assert_eq!(meta.display_name(), "foo");
+        }
+
+        #[test]
+        fn empty_dn_falls_back_to_info_hash() {
+            let buf = nameless_info();
+            let meta = Metadata::from_magnet(&buf, Some(""), &[]).unwrap();
+            assert_eq!(meta.display_name(), to_hex(&meta.info_hash()));
+        }
+    }
+
+    mod from_magnet {
+        use super::*;
+
+        #[test]
+        fn keeps_trackers() {
+            let buf = single_file_torrent(4, 4, 20);
+            let info = Metadata::parse(&buf).unwrap().info_bytes().to_vec();
+            let trackers = vec!["udp://tracker.example:6969".to_string()];
+            let meta = Metadata::from_magnet(&info, None, &trackers).unwrap();
+            assert_eq!(meta.announce_urls(), ["udp://tracker.example:6969"]);
+        }
+    }
+
+    mod safe_path {
+        use super::*;
+
+        fn entry(path: &[&'static [u8]]) -> FileEntry<'static> {
+            FileEntry {
+                length: 0,
+                path: path.to_vec(),
+                md5sum: None,
+            }
+        }
+
+        #[test]
+        fn normal_path() {
+            let file = entry(&[b"dir", b"a.txt"]);
+            assert_eq!(file.safe_path().unwrap(), PathBuf::from("dir/a.txt"));
+            assert_eq!(file.components(), &[&b"dir"[..], b"a.txt"]);
+        }
+
+        #[test]
+        fn rejects_parent_dir() {
+            assert!(entry(&[b"dir", b"..", b"etc"]).safe_path().is_err());
+        }
+
+        #[test]
+        fn rejects_empty_component() {
+            assert!(entry(&[b"dir", b"", b"a"]).safe_path().is_err());
+            assert!(entry(&[]).safe_path().is_err());
+        }
+
+        #[test]
+        fn rejects_absolute_and_nested_components() {
+            assert!(entry(&[b"/etc", b"passwd"]).safe_path().is_err());
+            assert!(entry(&[b"a/../../b"]).safe_path().is_err());
+            assert!(entry(&[b"..\\b"]).safe_path().is_err());
+            assert!(entry(&[b"."]).safe_path().is_err());
+        }
+    }
+
+    mod md5sum {
+        use super::*;
+
+        fn torrent_with_file(file_entries: &[u8]) -> Vec<u8> {
+            let mut buf = b"d8:announce1:a4:infod5:filesld6:lengthi4e".to_vec();
+            buf.extend_from_slice(file_entries);
+            buf.extend_from_slice(b"4:pathl1:aeee4:name3:foo12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            buf
+        }
+
+        #[test]
+        fn present() {
+            let buf = torrent_with_file(b"6:md5sum32:d41d8cd98f00b204e9800998ecf8427E");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(
+                meta.info.files()[0].md5sum,
+                Some([
+                    0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98, 0xec,
+                    0xf8, 0x42, 0x7e
+                ])
+            );
+        }
+
+        #[test]
+        fn absent() {
+            let buf = torrent_with_file(b"");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.info.files()[0].md5sum, None);
+        }
+
+        #[test]
+        fn wrong_length() {
+            let buf = torrent_with_file(b"6:md5sum4:d41d");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+
+        #[test]
+        fn not_hex() {
+            let buf = torrent_with_file(b"6:md5sum32:z41d8cd98f00b204e9800998ecf8427e");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+
+        #[test]
+        fn signed_digits() {
+            let buf = torrent_with_file(b"6:md5sum32:+a+a+a+a+a+a+a+a+a+a+a+a+a+a+a+a");
+            let err = Metadata::parse(&buf).unwrap_err();
+            assert!(err.to_string().ends_with("is not hex"), "{}", err);
+            let buf = torrent_with_file(b"6:md5sum32:-1d41d8cd98f00b204e9800998ecf842");
+            assert!(Metadata::parse(&buf).is_err());
+        }
+    }
+
+    mod utf8_variants {
+        use super::*;
+
+        fn torrent_with_info(info_entries: &[u8]) -> Vec<u8> {
+            let mut buf = b"d8:announce1:a4:infod".to_vec();
+            buf.extend_from_slice(info_entries);
+            buf.extend_from_slice(b"12:piece lengthi4e6:pieces20:");
+            buf.extend_from_slice(&[0xAB; 20]);
+            buf.extend_from_slice(b"ee");
+            buf
+        }
+
+        #[test]
+        fn prefers_utf8_name() {
+            let buf = torrent_with_info(b"6:lengthi4e4:name3:f\xffo10:name.utf-83:f\xc3\xb6");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.name_utf8().unwrap(), "f\u{f6}");
+        }
+
+        #[test]
+        fn ignores_invalid_utf8_name() {
+            let buf = torrent_with_info(b"6:lengthi4e4:name3:foo10:name.utf-83:f\xffo");
+            let meta = Metadata::parse(&buf).unwrap();
+            assert_eq!(meta.name_utf8().unwrap(), "foo");
+        }
+
+        #[test]
+        fn prefers_utf8_path() {
+            let buf = torrent_with_info(
+                b"5:filesld6:lengthi4e4:pathl1:a2:\xffbe10:path.utf-8l1:a2:cdeee4:name3:foo",
+            );
+            let meta = Metadata::parse(&buf).unwrap();
+            let expected: Vec<&[u8]> = vec![b"a", b"cd"];
+            assert_eq!(meta.info.files()[0].path, expected);
+        }
+
+        #[test]
+        fn falls_back_to_primary_path() {
+            let buf = torrent_with_info(b"5:filesld6:lengthi4e4:pathl1:a1:beee4:name3:foo");
+            let meta = Metadata::parse(&buf).unwrap();
+            let expected: Vec<&[u8]> = vec![b"a", b"b"];
+            assert_eq!(meta.info.files()[0].path, expected);
+        }
+    }
 }
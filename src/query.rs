@@ -0,0 +1,464 @@
+//! A small expression language for pulling fields out of a bencoded tree,
+//! e.g. `info.files[2].path.length()`.
+//!
+//! An expression is a chain of steps: `.key` looks up a dictionary key,
+//! `[2]` indexes a list, and `["piece length"]` looks up a key that isn't a
+//! plain name.  Keys may contain spaces, so `info.piece length` works too.
+//! A step ending in `()` calls a function on the value so far instead:
+//! `length()` counts a string's bytes or a list's or dictionary's entries,
+//! `keys()` lists a dictionary's keys and `type()` names the value's type.
+//! The empty expression is the whole tree.
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::alloc_prelude::*;
+use crate::bencode::{utf8_or_hex, BEncodedType};
+use crate::error::{invalid, Error, Result};
+
+/// A parsed query expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    Call(Function),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Length,
+    Keys,
+    Type,
+}
+
+/// Characters that end a plain key.
+const DELIMITERS: [char; 6] = ['.', '[', ']', '(', ')', '"'];
+
+impl Query {
+    pub fn parse(expr: &str) -> Result<Query> {
+        let mut steps = Vec::new();
+        let mut rest = expr.strip_prefix('.').unwrap_or(expr);
+        if rest.starts_with('.') {
+            return Err(syntax_error(expr, rest));
+        }
+        while !rest.is_empty() {
+            if let Some(bracketed) = rest.strip_prefix('[') {
+                let (step, after) =
+                    parse_bracket(bracketed).ok_or_else(|| syntax_error(expr, rest))?;
+                steps.push(step);
+                rest = after;
+                continue;
+            }
+            let name = match rest.strip_prefix('.') {
+                Some(name) => name,
+                None if steps.is_empty() => rest,
+                None => return Err(syntax_error(expr, rest)),
+            };
+            let end = name.find(DELIMITERS).unwrap_or(name.len());
+            if end == 0 {
+                return Err(syntax_error(expr, name));
+            }
+            let (key, after) = name.split_at(end);
+            match after.strip_prefix("()") {
+                Some(after) => {
+                    steps.push(Step::Call(Function::from_name(key)?));
+                    rest = after;
+                }
+                None => {
+                    steps.push(Step::Key(key.to_string()));
+                    rest = after;
+                }
+            }
+        }
+        Ok(Query { steps })
+    }
+
+    /// Runs the query against `value`.  Errors say which step failed, e.g.
+    /// `info.files[2]: index 2 is out of range for a list of 1`.
+    pub fn eval<'a>(&self, value: &BEncodedType<'a>) -> Result<BEncodedType<'a>> {
+        let mut current = Cow::Borrowed(value);
+        for (i, step) in self.steps.iter().enumerate() {
+            let applied = match &current {
+                Cow::Borrowed(value) => step.apply(value),
+                Cow::Owned(value) => step.apply(value).map(|x| Cow::Owned(x.into_owned())),
+            };
+            current = applied.map_err(|e| Error::AtPath {
+                path: Query {
+                    steps: self.steps[..=i].to_vec(),
+                }
+                .to_string(),
+                source: Box::new(e),
+            })?;
+        }
+        Ok(current.into_owned())
+    }
+}
+
+/// Reports the character at the start of `rest`, a suffix of `expr`, or
+/// that `expr` ended early if `rest` is empty.
+fn syntax_error(expr: &str, rest: &str) -> Error {
+    match rest.chars().next() {
+        Some(c) => invalid!(
+            "invalid query {:?}: unexpected {:?} at offset {}",
+            expr,
+            c,
+            expr.len() - rest.len()
+        ),
+        None => invalid!("invalid query {:?}: unexpected end of query", expr),
+    }
+}
+
+/// Parses what follows a `[`: a list index or a quoted key, then the `]`.
+fn parse_bracket(input: &str) -> Option<(Step, &str)> {
+    if let Some(quoted) = input.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        let rest = quoted[end + 1..].strip_prefix(']')?;
+        return Some((Step::Key(quoted[..end].to_string()), rest));
+    }
+    let end = input.find(']')?;
+    let index = &input[..end];
+    if !index.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    Some((Step::Index(index.parse().ok()?), &input[end + 1..]))
+}
+
+impl Step {
+    fn apply<'v, 'a>(&self, value: &'v BEncodedType<'a>) -> Result<Cow<'v, BEncodedType<'a>>> {
+        match self {
+            Step::Key(key) => value.dict_get(key).map(Cow::Borrowed),
+            Step::Index(index) => {
+                let items = value.as_list()?;
+                items.get(*index).map(Cow::Borrowed).ok_or_else(|| {
+                    invalid!(
+                        "index {} is out of range for a list of {}",
+                        index,
+                        items.len()
+                    )
+                })
+            }
+            Step::Call(function) => function.apply(value).map(Cow::Owned),
+        }
+    }
+}
+
+impl Function {
+    fn from_name(name: &str) -> Result<Function> {
+        match name {
+            "length" => Ok(Function::Length),
+            "keys" => Ok(Function::Keys),
+            "type" => Ok(Function::Type),
+            _ => Err(invalid!("unknown query function {}()", name)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Length => "length",
+            Function::Keys => "keys",
+            Function::Type => "type",
+        }
+    }
+
+    fn apply<'a>(self, value: &BEncodedType<'a>) -> Result<BEncodedType<'a>> {
+        match self {
+            Function::Length => match value {
+                BEncodedType::String(x) => Ok(BEncodedType::Integer(x.len() as i64)),
+                _ => match value.len() {
+                    Some(len) => Ok(BEncodedType::Integer(len as i64)),
+                    None => Err(value.wrong_type("a string, list or dictionary")),
+                },
+            },
+            Function::Keys => Ok(value
+                .as_dict()?
+                .iter()
+                .map(|(key, _)| BEncodedType::String(key))
+                .collect()),
+            Function::Type => Ok(BEncodedType::String(value.type_str().as_bytes())),
+        }
+    }
+}
+
+/// Writes the query back out in a form `Query::parse` accepts.
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            let dot = if i == 0 { "" } else { "." };
+            match step {
+                Step::Key(key) if key.is_empty() || key.contains(DELIMITERS) => {
+                    write!(f, "[\"{}\"]", key)?
+                }
+                Step::Key(key) => write!(f, "{}{}", dot, key)?,
+                Step::Index(index) => write!(f, "[{}]", index)?,
+                Step::Call(function) => write!(f, "{}{}()", dot, function.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes a query result as compact JSON.  Strings that are valid
+/// UTF-8 become JSON strings and any other string becomes `{"hex": "..."}`,
+/// since JSON can't hold raw bytes.  Integers are numbers, lists arrays and
+/// dictionaries objects, with any invalid UTF-8 in their keys replaced by
+/// U+FFFD.  This is for reading, where the short tag is clearer;
+/// `BEncodedType::to_json` tags bytes as `{"__bytes_hex__": "..."}` so its
+/// output can be converted back.
+pub fn to_json(value: &BEncodedType) -> String {
+    let mut out = String::new();
+    write_json(&mut out, value);
+    out
+}
+
+fn write_json(out: &mut String, value: &BEncodedType) {
+    match value {
+        BEncodedType::String(x) => match utf8_or_hex(x) {
+            Ok(text) => write_json_str(out, text),
+            Err(hex) => out.push_str(&format!("{{\"hex\":\"{}\"}}", hex)),
+        },
+        BEncodedType::Integer(x) => out.push_str(&x.to_string()),
+        BEncodedType::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        }
+        BEncodedType::Dictionary(entries) => {
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_str(out, &String::from_utf8_lossy(key));
+                out.push(':');
+                write_json(out, item);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_json_str(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::decode;
+
+    const TORRENT: &[u8] = b"d8:announce1:a4:infod5:filesld6:lengthi5e4:pathl1:a1:beed6:lengthi7e4:pathl1:ceee4:name3:foo12:piece lengthi4eee";
+
+    fn query<'a>(expr: &str, value: &BEncodedType<'a>) -> Result<BEncodedType<'a>> {
+        Query::parse(expr)?.eval(value)
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn steps() {
+            let query = Query::parse("info.files[2].path.length()").unwrap();
+            assert_eq!(
+                query.steps,
+                vec![
+                    Step::Key("info".to_string()),
+                    Step::Key("files".to_string()),
+                    Step::Index(2),
+                    Step::Key("path".to_string()),
+                    Step::Call(Function::Length),
+                ]
+            );
+        }
+
+        #[test]
+        fn quoted_and_spaced_keys() {
+            let quoted = Query::parse("info[\"piece length\"]").unwrap();
+            let spaced = Query::parse(".info.piece length").unwrap();
+            assert_eq!(quoted, spaced);
+        }
+
+        #[test]
+        fn empty_is_root() {
+            assert!(Query::parse("").unwrap().steps.is_empty());
+            assert!(Query::parse(".").unwrap().steps.is_empty());
+        }
+
+        #[test]
+        fn syntax_errors() {
+            let err = Query::parse("info..name").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid query \"info..name\": unexpected '.' at offset 5"
+            );
+            assert!(Query::parse("files[x]").is_err());
+            assert!(Query::parse("files[1").is_err());
+            assert!(Query::parse("info name()").is_err());
+        }
+
+        #[test]
+        fn trailing_dot() {
+            for expr in &["a.", "info.files."] {
+                let err = Query::parse(expr).unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    format!("invalid query {:?}: unexpected end of query", expr)
+                );
+            }
+        }
+
+        #[test]
+        fn doubled_dots() {
+            for expr in &["..", "..name"] {
+                let err = Query::parse(expr).unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    format!("invalid query {:?}: unexpected '.' at offset 1", expr)
+                );
+            }
+        }
+
+        #[test]
+        fn unknown_function() {
+            let err = Query::parse("info.size()").unwrap_err();
+            assert_eq!(err.to_string(), "unknown query function size()");
+        }
+
+        #[test]
+        fn display_round_trips() {
+            for expr in &[
+                "info.files[1].path",
+                "[\"a.b\"].keys()",
+                "info.piece length",
+            ] {
+                assert_eq!(Query::parse(expr).unwrap().to_string(), *expr);
+            }
+        }
+    }
+
+    mod to_json {
+        use super::*;
+
+        #[test]
+        fn scalars_and_lists() {
+            let value = decode(b"li-5e3:foo2:\xff\x00le0:e").unwrap();
+            assert_eq!(to_json(&value), r#"[-5,"foo",{"hex":"ff00"},[],""]"#);
+        }
+
+        #[test]
+        fn dictionaries() {
+            let value = decode(b"d1:ai1e1:bd1:c1:xee").unwrap();
+            assert_eq!(to_json(&value), r#"{"a":1,"b":{"c":"x"}}"#);
+        }
+
+        #[test]
+        fn escapes_strings() {
+            let value = decode(b"8:a\"b\\\n\x01\xc3\xa9").unwrap();
+            assert_eq!(to_json(&value), r#""a\"b\\\n\u0001é""#);
+        }
+    }
+
+    mod eval {
+        use super::*;
+
+        #[test]
+        fn path() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(
+                query("info.name", &value).unwrap(),
+                BEncodedType::string("foo")
+            );
+            assert_eq!(query("", &value).unwrap(), value);
+        }
+
+        #[test]
+        fn index() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(
+                query("info.files[1].length", &value).unwrap(),
+                BEncodedType::Integer(7)
+            );
+            assert_eq!(
+                query("info.files[0].path.length()", &value).unwrap(),
+                BEncodedType::Integer(2)
+            );
+        }
+
+        #[test]
+        fn string_length() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(
+                query("info.name.length()", &value).unwrap(),
+                BEncodedType::Integer(3)
+            );
+            let err = query("info[\"piece length\"].length()", &value).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "info.piece length.length(): Type is Integer, not a string, list or dictionary"
+            );
+        }
+
+        #[test]
+        fn keys() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(
+                query("info.keys()", &value).unwrap(),
+                BEncodedType::list(
+                    ["files", "name", "piece length"]
+                        .iter()
+                        .map(|x| BEncodedType::string(x))
+                )
+            );
+            assert_eq!(
+                query("info.keys().length()", &value).unwrap(),
+                BEncodedType::Integer(3)
+            );
+        }
+
+        #[test]
+        fn type_name() {
+            let value = decode(TORRENT).unwrap();
+            assert_eq!(
+                query("info[\"piece length\"].type()", &value).unwrap(),
+                BEncodedType::string("Integer")
+            );
+        }
+
+        #[test]
+        fn errors_name_the_failing_step() {
+            let value = decode(TORRENT).unwrap();
+            let err = query("info.files[2].length", &value).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "info.files[2]: index 2 is out of range for a list of 2"
+            );
+            let err = query("info.length", &value).unwrap_err();
+            assert_eq!(err.to_string(), "info.length: Key not found");
+            let err = query("announce.keys()", &value).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "announce.keys(): Type is String, not a dictionary"
+            );
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! A small SHA-1 implementation, enough to compute torrent info-hashes
+//! without pulling in a crypto dependency.
+
+use crate::alloc_prelude::*;
+
+/// Length of a SHA-1 digest in bytes.
+pub const DIGEST_LEN: usize = 20;
+
+const BLOCK_LEN: usize = 64;
+
+/// An incremental SHA-1 hasher.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Sha1::new()
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: [
+                0x6745_2301,
+                0xEFCD_AB89,
+                0x98BA_DCFE,
+                0x1032_5476,
+                0xC3D2_E1F0,
+            ],
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    /// Feeds more data into the hash.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        if self.block_len > 0 {
+            let take = (BLOCK_LEN - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < BLOCK_LEN {
+                return;
+            }
+            let block = self.block;
+            self.compress(&block);
+            self.block_len = 0;
+        }
+        let mut blocks = data.chunks_exact(BLOCK_LEN);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    /// Pads the message and returns its digest.
+    pub fn finish(mut self) -> [u8; DIGEST_LEN] {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != BLOCK_LEN - 8 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+
+        let mut digest = [0; DIGEST_LEN];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+/// Lets the hasher be fed with `write_all`, `io::copy` and the like.
+#[cfg(feature = "std")]
+impl std::io::Write for Sha1 {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes `data` in one go.
+pub fn sha1(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Formats a digest as lowercase hex.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Formats a digest as unpadded RFC 4648 base32, the other form magnet
+/// links accept for info-hashes.
+pub fn to_base32(digest: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &byte in digest {
+        bits = (bits << 8) | u32::from(byte);
+        bit_count += 8;
+        while bit_count >= 5 {
+            bit_count -= 5;
+            out.push(ALPHABET[(bits >> bit_count) as usize & 31] as char);
+        }
+    }
+    if bit_count > 0 {
+        out.push(ALPHABET[(bits << (5 - bit_count)) as usize & 31] as char);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod sha1 {
+        use super::*;
+
+        #[test]
+        fn empty() {
+            assert_eq!(
+                to_hex(&sha1(b"")),
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+            );
+        }
+
+        #[test]
+        fn abc() {
+            assert_eq!(
+                to_hex(&sha1(b"abc")),
+                "a9993e364706816aba3e25717850c26c9cd0d89d"
+            );
+        }
+
+        #[test]
+        fn two_blocks() {
+            assert_eq!(
+                to_hex(&sha1(
+                    b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+                )),
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+            );
+        }
+
+        #[test]
+        fn incremental_matches_one_shot() {
+            let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+            let mut hasher = Sha1::new();
+            for chunk in data.chunks(7) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finish(), sha1(&data));
+        }
+    }
+
+    mod to_base32 {
+        use super::*;
+
+        #[test]
+        fn rfc_4648_vectors() {
+            assert_eq!(to_base32(b""), "");
+            assert_eq!(to_base32(b"f"), "MY");
+            assert_eq!(to_base32(b"foob"), "MZXW6YQ");
+            assert_eq!(to_base32(b"foobar"), "MZXW6YTBOI");
+        }
+
+        #[test]
+        fn info_hash_is_32_chars() {
+            assert_eq!(to_base32(&sha1(b"abc")).len(), 32);
+        }
+    }
+}
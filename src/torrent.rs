@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+#[cfg(feature = "gzip")]
+use crate::gzip;
+use crate::metadata::{FileEntry, Metadata};
+use crate::sha1::{self, sha1};
+
+/// A parsed torrent that owns its bytes, for when keeping the buffer a
+/// `Metadata` borrows from alive is inconvenient.  Fields are kept as
+/// offsets into the buffer rather than references.
+pub struct Torrent {
+    bytes: Vec<u8>,
+    name: Range<usize>,
+    announce: Option<Range<usize>>,
+    info: Range<usize>,
+    files: Vec<FileSpans>,
+}
+
+/// A `FileEntry` with its path components as offsets.
+struct FileSpans {
+    length: u64,
+    path: Vec<Range<usize>>,
+    md5sum: Option<[u8; 16]>,
+}
+
+impl Torrent {
+    /// Parses `bytes`, failing just like `Metadata::parse` would.  With the
+    /// `gzip` feature, gzipped bytes are decompressed first.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Torrent> {
+        #[cfg(feature = "gzip")]
+        let bytes = if gzip::is_gzip(&bytes) {
+            gzip::decompress(&bytes)?
+        } else {
+            bytes
+        };
+        let meta = Metadata::parse(&bytes)?;
+        let name = span(&bytes, meta.name_bytes());
+        let announce = meta.announce.map(|x| span(&bytes, x.as_bytes()));
+        let info = span(&bytes, meta.info_bytes());
+        let files = meta
+            .info
+            .files()
+            .iter()
+            .map(|file| FileSpans {
+                length: file.length,
+                path: file.path.iter().map(|x| span(&bytes, x)).collect(),
+                md5sum: file.md5sum,
+            })
+            .collect();
+
+        Ok(Torrent {
+            bytes,
+            name,
+            announce,
+            info,
+            files,
+        })
+    }
+
+    /// Reads and parses the torrent file at `path`.  IO errors say which
+    /// file couldn't be read.
+    pub fn open(path: impl AsRef<Path>) -> Result<Torrent> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|source| Error::Open {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Torrent::from_bytes(bytes)
+    }
+
+    /// The full metadata, borrowing from this torrent's bytes.
+    pub fn metadata(&self) -> Metadata<'_> {
+        Metadata::parse(&self.bytes).expect("the buffer parsed when the torrent was built")
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn name_bytes(&self) -> &[u8] {
+        &self.bytes[self.name.clone()]
+    }
+
+    /// The torrent's name, with invalid UTF-8 replaced by U+FFFD.
+    pub fn name_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.name_bytes())
+    }
+
+    pub fn announce(&self) -> Option<&str> {
+        // `Metadata` already checked this range is UTF-8.
+        self.announce
+            .clone()
+            .map(|x| std::str::from_utf8(&self.bytes[x]).expect("announce is UTF-8"))
+    }
+
+    pub fn info_bytes(&self) -> &[u8] {
+        &self.bytes[self.info.clone()]
+    }
+
+    /// The SHA-1 of the raw `info` dictionary, which identifies the torrent.
+    pub fn info_hash(&self) -> [u8; sha1::DIGEST_LEN] {
+        sha1(self.info_bytes())
+    }
+
+    /// The files of a multi-file torrent, empty for single-file torrents.
+    pub fn files(&self) -> Vec<FileEntry<'_>> {
+        self.files
+            .iter()
+            .map(|file| FileEntry {
+                length: file.length,
+                path: file.path.iter().map(|x| &self.bytes[x.clone()]).collect(),
+                md5sum: file.md5sum,
+            })
+            .collect()
+    }
+}
+
+impl Debug for Torrent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Torrent")
+            .field("name", &self.name_lossy())
+            .field("announce", &self.announce())
+            .field("files", &self.files.len())
+            .finish()
+    }
+}
+
+/// Where `part`, a slice borrowed from `whole`, sits within it.
+fn span(whole: &[u8], part: &[u8]) -> Range<usize> {
+    let start = part.as_ptr() as usize - whole.as_ptr() as usize;
+    debug_assert!(start + part.len() <= whole.len());
+    start..start + part.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multi_file_torrent() -> Vec<u8> {
+        let mut buf = b"d8:announce17:http://t/announce4:infod5:filesl".to_vec();
+        buf.extend_from_slice(b"d6:lengthi5e4:pathl3:dir1:aee");
+        buf.extend_from_slice(b"e4:name3:foo12:piece lengthi8e6:pieces20:");
+        buf.extend_from_slice(&[0xAB; 20]);
+        buf.extend_from_slice(b"ee");
+        buf
+    }
+
+    mod from_bytes {
+        use super::*;
+
+        #[test]
+        fn mirrors_metadata() {
+            let buf = multi_file_torrent();
+            let meta = Metadata::parse(&buf).unwrap();
+            let torrent = Torrent::from_bytes(buf.clone()).unwrap();
+            assert_eq!(torrent.name_bytes(), meta.name_bytes());
+            assert_eq!(torrent.announce(), meta.announce);
+            assert_eq!(torrent.info_hash(), meta.info_hash());
+            assert_eq!(torrent.files(), meta.info.files());
+        }
+
+        #[cfg(feature = "gzip")]
+        #[test]
+        fn gzipped_matches_plain() {
+            let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+            let plain = Torrent::open(fixtures.join("demo.torrent")).unwrap();
+            let gzipped = Torrent::open(fixtures.join("demo.torrent.gz")).unwrap();
+            assert_eq!(gzipped.as_bytes(), plain.as_bytes());
+            assert_eq!(gzipped.info_hash(), plain.info_hash());
+            assert_eq!(gzipped.name_lossy(), "demo.txt");
+        }
+
+        #[test]
+        fn invalid_torrent() {
+            assert!(Torrent::from_bytes(b"d4:infodee".to_vec()).is_err());
+        }
+    }
+
+    mod open {
+        use super::*;
+
+        #[test]
+        fn outlives_the_original_buffer() {
+            let path =
+                std::env::temp_dir().join(format!("tquery-open-{}.torrent", std::process::id()));
+            let buf = multi_file_torrent();
+            fs::write(&path, &buf).unwrap();
+            drop(buf);
+
+            let torrent = Torrent::open(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(torrent.name_lossy(), "foo");
+            assert_eq!(torrent.announce(), Some("http://t/announce"));
+            assert_eq!(torrent.files()[0].path, vec![&b"dir"[..], b"a"]);
+            assert_eq!(torrent.metadata().info.piece_count(), 1);
+        }
+
+        #[test]
+        fn missing_file_names_the_path() {
+            let path = std::env::temp_dir().join("tquery-does-not-exist.torrent");
+            let err = Torrent::open(&path).unwrap_err();
+            let message = format!("{:#}", err);
+            assert!(message.contains("failed to read torrent file"));
+            assert!(message.contains("tquery-does-not-exist.torrent"));
+        }
+    }
+}
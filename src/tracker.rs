@@ -0,0 +1,318 @@
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use crate::bencode::{decode, BEncodedType};
+use crate::error::{invalid, Result};
+
+/// A compact IPv4 peer is a 4 byte address followed by a 2 byte port (BEP-23).
+const COMPACT_PEER_LEN: usize = 6;
+
+/// A compact IPv6 peer is a 16 byte address followed by a 2 byte port (BEP-7).
+const COMPACT_PEER6_LEN: usize = 18;
+
+/// A tracker's reply to an announce request.
+#[derive(Debug, PartialEq)]
+pub struct AnnounceResponse {
+    /// Seconds the client should wait between regular announces.
+    pub interval: u32,
+    /// Seconds the client must wait before announcing again, if given.
+    pub min_interval: Option<u32>,
+    /// Number of seeders, if the tracker reported it.
+    pub complete: Option<u32>,
+    /// Number of leechers, if the tracker reported it.
+    pub incomplete: Option<u32>,
+    /// Peers from both `peers` and `peers6`, IPv4 ones first.
+    pub peers: Vec<SocketAddr>,
+}
+
+impl AnnounceResponse {
+    /// Decodes a tracker's bencoded announce reply.  A reply carrying a
+    /// `failure reason` is returned as an error with that reason.
+    pub fn parse(buf: &[u8]) -> Result<AnnounceResponse> {
+        let be = decode(buf)?;
+        if let Some(reason) = be.dict_get_opt("failure reason")? {
+            return Err(invalid!(
+                "Tracker returned a failure: {}",
+                String::from_utf8_lossy(reason.as_str_bytes()?)
+            ));
+        }
+
+        let interval = be.dict_get("interval")?.as_u32()?;
+        let min_interval = optional_u32(&be, "min interval")?;
+        let complete = optional_u32(&be, "complete")?;
+        let incomplete = optional_u32(&be, "incomplete")?;
+        let peers = peers_from_response(&be)?;
+
+        Ok(AnnounceResponse {
+            interval,
+            min_interval,
+            complete,
+            incomplete,
+            peers,
+        })
+    }
+}
+
+fn optional_u32(dict: &BEncodedType, key: &str) -> Result<Option<u32>> {
+    dict.dict_get_opt(key)?.map(|x| x.as_u32()).transpose()
+}
+
+/// Collects the peers from a response's `peers` and `peers6` keys, either of
+/// which may be absent.
+pub fn peers_from_response(response: &BEncodedType) -> Result<Vec<SocketAddr>> {
+    let mut peers = match response.dict_get_opt("peers")? {
+        Some(peers) => parse_peers(peers)?,
+        None => Vec::new(),
+    };
+    if let Some(peers6) = response.dict_get_opt("peers6")? {
+        peers.extend(parse_peers6(peers6)?.into_iter().map(SocketAddr::V6));
+    }
+    Ok(peers)
+}
+
+/// Reads a tracker's `peers` value, which is either a list of dictionaries
+/// with `ip` and `port` keys, or a compact string of packed IPv4 addresses.
+pub fn parse_peers(value: &BEncodedType) -> Result<Vec<SocketAddr>> {
+    match value {
+        BEncodedType::String(compact) => Ok(parse_compact_peers(compact)?
+            .into_iter()
+            .map(SocketAddr::V4)
+            .collect()),
+        BEncodedType::List(peers) => peers.iter().map(parse_peer_dict).collect(),
+        _ => Err(invalid!(
+            "Type is {}, expected a peer list or compact string",
+            value.type_str()
+        )),
+    }
+}
+
+fn parse_compact_peers(compact: &[u8]) -> Result<Vec<SocketAddrV4>> {
+    if !compact.len().is_multiple_of(COMPACT_PEER_LEN) {
+        return Err(invalid!(
+            "Compact peers are {} bytes long, which is not a multiple of {}",
+            compact.len(),
+            COMPACT_PEER_LEN
+        ));
+    }
+    Ok(compact
+        .chunks_exact(COMPACT_PEER_LEN)
+        .map(|peer| {
+            let ip = Ipv4Addr::new(peer[0], peer[1], peer[2], peer[3]);
+            let port = u16::from_be_bytes([peer[4], peer[5]]);
+            SocketAddrV4::new(ip, port)
+        })
+        .collect())
+}
+
+/// Reads a compact `peers6` string of packed IPv6 addresses.
+pub fn parse_peers6(value: &BEncodedType) -> Result<Vec<SocketAddrV6>> {
+    let compact = value.as_str_bytes()?;
+    if !compact.len().is_multiple_of(COMPACT_PEER6_LEN) {
+        return Err(invalid!(
+            "Compact IPv6 peers are {} bytes long, which is not a multiple of {}",
+            compact.len(),
+            COMPACT_PEER6_LEN
+        ));
+    }
+    Ok(compact
+        .chunks_exact(COMPACT_PEER6_LEN)
+        .map(|peer| {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&peer[..16]);
+            let port = u16::from_be_bytes([peer[16], peer[17]]);
+            SocketAddrV6::new(Ipv6Addr::from(ip), port, 0, 0)
+        })
+        .collect())
+}
+
+fn parse_peer_dict(peer: &BEncodedType) -> Result<SocketAddr> {
+    let ip = peer.dict_get("ip")?.as_str()?;
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|e| invalid!("Invalid peer address {:?}: {}", ip, e))?;
+    let port = peer.dict_get("port")?.as_int()?;
+    let port = u16::try_from(port).map_err(|_| invalid!("Invalid peer port {}", port))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Derives a tracker's scrape URL from its announce URL by the usual
+/// convention: a last path segment of exactly `announce` becomes `scrape`,
+/// keeping any query string.  Trackers that don't follow it have no known
+/// scrape URL.
+pub fn scrape_url(announce: &str) -> Option<String> {
+    let path_start = announce.find("://")? + 3;
+    let end = announce.find(['?', '#']).unwrap_or(announce.len());
+    let (base, suffix) = announce.split_at(end);
+    let segment_start = base.rfind('/').filter(|&i| i >= path_start)? + 1;
+    if &base[segment_start..] != "announce" {
+        return None;
+    }
+    Some(format!("{}scrape{}", &base[..segment_start], suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(a: u8, b: u8, c: u8, d: u8, port: u16) -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(a, b, c, d), port))
+    }
+
+    mod announce_response {
+        use super::*;
+
+        #[test]
+        fn success() {
+            let buf = b"d8:completei5e10:incompletei3e8:intervali1800e12:min intervali60e5:peers6:\x7f\x00\x00\x01\x1a\xe1e";
+            assert_eq!(
+                AnnounceResponse::parse(buf).unwrap(),
+                AnnounceResponse {
+                    interval: 1800,
+                    min_interval: Some(60),
+                    complete: Some(5),
+                    incomplete: Some(3),
+                    peers: vec![v4(127, 0, 0, 1, 6881)],
+                }
+            );
+        }
+
+        #[test]
+        fn mixed_peers_and_peers6() {
+            let mut buf = b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe16:peers618:".to_vec();
+            buf.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+            buf.extend_from_slice(b"\x1a\xe2e");
+            let response = AnnounceResponse::parse(&buf).unwrap();
+            assert_eq!(
+                response.peers,
+                vec![
+                    v4(127, 0, 0, 1, 6881),
+                    SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 6882),
+                ]
+            );
+        }
+
+        #[test]
+        fn minimal() {
+            let response = AnnounceResponse::parse(b"d8:intervali900e5:peers0:e").unwrap();
+            assert_eq!(response.interval, 900);
+            assert_eq!(response.min_interval, None);
+            assert_eq!(response.complete, None);
+            assert!(response.peers.is_empty());
+        }
+
+        #[test]
+        fn failure_reason() {
+            let buf = b"d14:failure reason17:torrent not founde";
+            let err = AnnounceResponse::parse(buf).unwrap_err();
+            assert!(err.to_string().contains("torrent not found"));
+        }
+
+        #[test]
+        fn missing_interval() {
+            assert!(AnnounceResponse::parse(b"d5:peers0:e").is_err());
+        }
+    }
+
+    mod parse_peers {
+        use super::*;
+
+        #[test]
+        fn compact() {
+            let value = BEncodedType::String(b"\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x00\x50");
+            assert_eq!(
+                parse_peers(&value).unwrap(),
+                vec![v4(127, 0, 0, 1, 6881), v4(10, 0, 0, 2, 80)]
+            );
+        }
+
+        #[test]
+        fn compact_with_bad_length() {
+            let value = BEncodedType::String(b"\x7f\x00\x00\x01\x1a");
+            assert!(parse_peers(&value).is_err());
+        }
+
+        #[test]
+        fn dictionaries() {
+            let value = decode(b"ld2:ip9:127.0.0.14:porti6881eed2:ip3:::14:porti80eee").unwrap();
+            assert_eq!(
+                parse_peers(&value).unwrap(),
+                vec![
+                    v4(127, 0, 0, 1, 6881),
+                    SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 80),
+                ]
+            );
+        }
+
+        #[test]
+        fn dictionary_with_bad_port() {
+            let value = decode(b"ld2:ip9:127.0.0.14:porti70000eee").unwrap();
+            assert!(parse_peers(&value).is_err());
+        }
+
+        #[test]
+        fn wrong_type() {
+            assert!(parse_peers(&BEncodedType::Integer(1)).is_err());
+        }
+    }
+
+    mod parse_peers6 {
+        use super::*;
+
+        #[test]
+        fn compact() {
+            let mut buf = Ipv6Addr::LOCALHOST.octets().to_vec();
+            buf.extend_from_slice(b"\x1a\xe1");
+            assert_eq!(
+                parse_peers6(&BEncodedType::String(&buf)).unwrap(),
+                vec![SocketAddrV6::new(Ipv6Addr::LOCALHOST, 6881, 0, 0)]
+            );
+        }
+
+        #[test]
+        fn bad_length() {
+            let buf = [0u8; 17];
+            assert!(parse_peers6(&BEncodedType::String(&buf)).is_err());
+        }
+    }
+
+    mod scrape_url {
+        use super::*;
+
+        #[test]
+        fn plain() {
+            assert_eq!(
+                scrape_url("http://t/announce").as_deref(),
+                Some("http://t/scrape")
+            );
+        }
+
+        #[test]
+        fn keeps_query_string() {
+            assert_eq!(
+                scrape_url("http://t/announce?x=1").as_deref(),
+                Some("http://t/scrape?x=1")
+            );
+        }
+
+        #[test]
+        fn nested_path() {
+            assert_eq!(
+                scrape_url("udp://t:80/x/announce").as_deref(),
+                Some("udp://t:80/x/scrape")
+            );
+        }
+
+        #[test]
+        fn other_last_segment() {
+            assert_eq!(scrape_url("http://t/announce/x"), None);
+            assert_eq!(scrape_url("http://t/announce.php"), None);
+            assert_eq!(scrape_url("http://t/"), None);
+        }
+
+        #[test]
+        fn announce_host() {
+            assert_eq!(scrape_url("http://announce"), None);
+            assert_eq!(scrape_url("http://t?q=/announce"), None);
+        }
+    }
+}
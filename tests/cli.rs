@@ -0,0 +1,176 @@
+//! Runs the built binary against fixture files.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const DEMO: &str = "tests/fixtures/demo.torrent";
+const HELLO: &str = "tests/fixtures/hello.torrent";
+/// 40000 bytes in 16 KiB pieces, but with only one piece hash.
+const TRUNCATED: &str = "tests/fixtures/truncated.torrent";
+
+fn tquery(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_tquery"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+/// Like `tquery`, but returns stderr instead of stdout.
+fn tquery_stderr(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_tquery"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// Writes `contents` to a scratch file named `name`.
+fn scratch_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn info_hash_hex() {
+    let (ok, stdout) = tquery(&["info-hash", DEMO]);
+    assert!(ok);
+    assert_eq!(stdout, "5c14691c7957d510721c464135997ec0a4ebd080\n");
+}
+
+#[test]
+fn info_hash_base32() {
+    let (ok, stdout) = tquery(&["info-hash", "--base32", DEMO]);
+    assert!(ok);
+    assert_eq!(stdout, "LQKGSHDZK7KRA4Q4IZATLGL6YCSOXUEA\n");
+}
+
+#[test]
+fn info_hash_missing_file() {
+    let (ok, stdout) = tquery(&["info-hash", "tests/fixtures/missing.torrent"]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn empty_file() {
+    let path = scratch_file("empty.torrent", b"");
+    let path = path.to_str().unwrap();
+    let (ok, stderr) = tquery_stderr(&["info-hash", path]);
+    assert!(!ok);
+    assert!(stderr.contains(&format!("{} is empty or not bencoded", path)));
+}
+
+#[test]
+fn whitespace_only_file() {
+    let path = scratch_file("blank.torrent", b" \n\t\r\n");
+    let path = path.to_str().unwrap();
+    let (ok, stderr) = tquery_stderr(&["info-hash", path]);
+    assert!(!ok);
+    assert!(stderr.contains(&format!("{} is empty or not bencoded", path)));
+}
+
+#[test]
+fn other_decode_errors_pass_through() {
+    let path = scratch_file("overflow.torrent", b"99999999999999999999999:foo");
+    let (ok, stderr) = tquery_stderr(&["info-hash", path.to_str().unwrap()]);
+    assert!(!ok);
+    assert!(!stderr.contains("is empty or not bencoded"), "{}", stderr);
+}
+
+#[test]
+fn tree_collapses_pieces() {
+    let (ok, stdout) = tquery(&["--tree", DEMO]);
+    assert!(ok);
+    assert!(stdout.starts_with("Dictionary ("));
+    assert!(stdout.contains("\n    name: String \"demo.txt\"\n"));
+    assert!(stdout.contains("\n    pieces: <pieces: 3 × 20 bytes>\n"));
+}
+
+#[test]
+fn tree_only_collapses_info_pieces() {
+    let mut buf =
+        b"d8:announce1:a4:infod6:lengthi5e4:name3:foo12:piece lengthi16384e6:pieces20:".to_vec();
+    buf.extend_from_slice(&[0xAB; 20]);
+    buf.extend_from_slice(b"e6:pieces20:xxxxxxxxxxxxxxxxxxxxe");
+    let path = scratch_file("user-pieces.torrent", &buf);
+    let (ok, stdout) = tquery(&["--tree", path.to_str().unwrap()]);
+    assert!(ok);
+    assert!(stdout.contains("\n    pieces: <pieces: 1 × 20 bytes>\n"));
+    assert!(stdout.contains("\n  pieces: String \"xxxxxxxxxxxxxxxxxxxx\"\n"));
+}
+
+#[test]
+fn tree_redacts_pieces() {
+    let (ok, stdout) = tquery(&["--tree", "--redact", DEMO]);
+    assert!(ok);
+    assert!(stdout.contains("\n    name: String \"demo.txt\"\n"));
+    assert!(stdout.contains("\n    pieces: String \"<redacted 3 pieces>\"\n"));
+}
+
+#[test]
+fn query_prints_result() {
+    let (ok, stdout) = tquery(&["--query", "info.name", DEMO]);
+    assert!(ok);
+    assert_eq!(stdout, "demo.txt\n");
+
+    let (ok, stdout) = tquery(&["--query", "info.keys()", DEMO]);
+    assert!(ok);
+    assert_eq!(stdout, "[\n  length\n  name\n  piece length\n  pieces\n]\n");
+}
+
+#[test]
+fn query_json_hex_encodes_binary_strings() {
+    // The one piece hash is the SHA-1 of "hello", which isn't valid UTF-8.
+    let (ok, stdout) = tquery(&["--query", "--json", "info.pieces", HELLO]);
+    assert!(ok);
+    assert_eq!(
+        stdout,
+        "{\"hex\":\"aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d\"}\n"
+    );
+
+    let (ok, stdout) = tquery(&["--query", "--json", "info.name", HELLO]);
+    assert!(ok);
+    assert_eq!(stdout, "\"hello.txt\"\n");
+}
+
+#[test]
+fn query_reports_misses() {
+    let (ok, stdout) = tquery(&["--query", "info.files[0]", DEMO]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn query_reports_trailing_dot() {
+    let (ok, stderr) = tquery_stderr(&["--query", "info.", DEMO]);
+    assert!(!ok);
+    assert!(stderr.contains("unexpected end of query"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn lint_lists_diagnostics() {
+    let (ok, stdout) = tquery(&["--lint", DEMO]);
+    assert!(ok);
+    assert_eq!(stdout, "info: torrent has no creation date\n");
+}
+
+#[test]
+fn lint_fails_on_errors() {
+    let (ok, stdout) = tquery(&["--lint", TRUNCATED]);
+    assert!(!ok);
+    assert_eq!(
+        stdout,
+        "error: Expected 3 pieces for 40000 bytes with piece length 16384, found 1\n\
+         info: torrent has no creation date\n"
+    );
+}
@@ -0,0 +1,118 @@
+//! Randomized round-trip and robustness tests for the decoder.
+//!
+//! These use a small fixed-seed PRNG rather than a property testing crate so
+//! failures are reproducible from the seed alone.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use tquery::bencode::{decode, encode, OwnedBEncoded};
+
+/// xorshift64*, good enough to shake out parser edge cases.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn bytes(&mut self, max_len: u64) -> Vec<u8> {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    fn integer(&mut self) -> i64 {
+        match self.below(4) {
+            0 => 0,
+            1 => i64::MAX,
+            2 => i64::MIN,
+            _ => self.next() as i64,
+        }
+    }
+
+    fn tree(&mut self, depth: u32) -> OwnedBEncoded {
+        let kind = if depth == 0 {
+            self.below(2)
+        } else {
+            self.below(4)
+        };
+        match kind {
+            0 => OwnedBEncoded::String(self.bytes(24)),
+            1 => OwnedBEncoded::Integer(self.integer()),
+            2 => {
+                let len = self.below(6);
+                OwnedBEncoded::List((0..len).map(|_| self.tree(depth - 1)).collect())
+            }
+            _ => {
+                let len = self.below(6);
+                let entries = (0..len).map(|_| (self.bytes(8), self.tree(depth - 1)));
+                OwnedBEncoded::Dictionary(entries.collect::<BTreeMap<_, _>>())
+            }
+        }
+    }
+}
+
+#[test]
+fn encode_decode_round_trip() {
+    let mut rng = Rng(0x5eed_0001);
+    for _ in 0..2_000 {
+        let tree = rng.tree(5);
+        let encoded = encode(&tree.as_borrowed());
+        let decoded =
+            decode(&encoded).unwrap_or_else(|e| panic!("failed to decode {:?}: {}", encoded, e));
+        assert_eq!(OwnedBEncoded::from(&decoded), tree);
+        assert_eq!(decoded.encoded_len(), encoded.len());
+    }
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut rng = Rng(0x5eed_0002);
+    for _ in 0..20_000 {
+        let buf = rng.bytes(64);
+        let _ = decode(&buf);
+    }
+}
+
+#[test]
+fn mutated_encodings_never_panic() {
+    let mut rng = Rng(0x5eed_0003);
+    for _ in 0..5_000 {
+        let mut buf = encode(&rng.tree(4).as_borrowed());
+        for _ in 0..=rng.below(4) {
+            if buf.is_empty() {
+                break;
+            }
+            let at = rng.below(buf.len() as u64) as usize;
+            match rng.below(3) {
+                0 => buf[at] = rng.next() as u8,
+                1 => {
+                    buf.remove(at);
+                }
+                _ => buf.insert(at, b"0123456789:ilde-"[rng.below(16) as usize]),
+            }
+        }
+        let _ = decode(&buf);
+    }
+}
+
+#[test]
+fn regression_inputs_fail_cleanly() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/regressions");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let buf = fs::read(&path).unwrap();
+        assert!(decode(&buf).is_err(), "{} decoded", path.display());
+        count += 1;
+    }
+    assert!(count > 0);
+}
@@ -0,0 +1,33 @@
+//! Runs the std test suite again with the `minimal` parser in place of nom,
+//! so both backends are held to the same tests.
+//!
+//! Like `no_std.rs`, this runs cargo on the crate itself, into its own
+//! target directory.  It names the test targets to run rather than taking
+//! them all, which would include this one again.
+
+use std::path::Path;
+use std::process::Command;
+
+fn cargo_test_minimal(targets: &[&str]) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env!("CARGO"))
+        .args(["test", "--offline", "--no-default-features"])
+        .args(["--features", "std,minimal,gzip"])
+        .args(targets)
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", manifest_dir.join("target/minimal"))
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "cargo test {:?} with the minimal parser failed",
+        targets
+    );
+}
+
+#[test]
+fn same_suite_as_nom() {
+    cargo_test_minimal(&["--lib", "--test", "cli", "--test", "fuzz"]);
+    cargo_test_minimal(&["--doc"]);
+}
@@ -0,0 +1,39 @@
+//! Checks that the bencode core still builds without std, with either parser,
+//! and that its own tests pass that way.
+//!
+//! This runs cargo on the library itself, into its own target directory so it
+//! doesn't fight the outer build over the lock.  The tests link std to run,
+//! but with the `std` feature off they only reach the no_std API.
+
+use std::path::Path;
+use std::process::Command;
+
+fn cargo_without_std(subcommand: &str, features: &str) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env!("CARGO"))
+        .args([subcommand, "--lib", "--offline", "--no-default-features"])
+        .args(["--features", features])
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", manifest_dir.join("target/no_std"))
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "cargo {} without std, with {:?}, failed",
+        subcommand,
+        features
+    );
+}
+
+#[test]
+fn nom_parser() {
+    cargo_without_std("check", "nom");
+    cargo_without_std("test", "nom");
+}
+
+#[test]
+fn minimal_parser() {
+    cargo_without_std("check", "minimal,gzip,json");
+    cargo_without_std("test", "minimal,gzip,json");
+}